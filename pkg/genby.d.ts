@@ -1,15 +0,0 @@
-/* tslint:disable */
-export function create(arg0: number, arg1: number): void;
-
-export function tick(arg0: number): void;
-
-export function size(): Uint32Array;
-
-export function get_pixels(arg0: boolean, arg1: boolean, arg2: boolean, arg3: boolean): Uint8Array;
-
-export function get_heights(arg0: boolean): Float32Array;
-
-export function get_wind_directions(): Float32Array;
-
-export function alter_world(arg0: number, arg1: number, arg2: number, arg3: number, arg4: number, arg5: number): void;
-
@@ -1,11 +0,0 @@
-/* tslint:disable */
-export const memory: WebAssembly.Memory;
-export function __wbindgen_global_argument_ptr(): number;
-export function create(a: number, b: number): void;
-export function tick(a: number): void;
-export function size(a: number): void;
-export function get_pixels(a: number, b: number, c: number, d: number, e: number): void;
-export function get_heights(a: number, b: number): void;
-export function get_wind_directions(a: number): void;
-export function alter_world(a: number, b: number, c: number, d: number, e: number, f: number): void;
-export function __wbindgen_free(a: number, b: number): void;
@@ -1,12 +1,15 @@
 use cell;
+use soil;
 use vec;
 
 #[derive(Copy, Clone, PartialEq)]
 pub enum BiomeType {
+    Ocean,
     Lake,
     Forest,
     Mountain,
     Wetland,
+    Glacier,
     Nob,
 }
 
@@ -17,30 +20,62 @@ pub struct BiomeTags {
     high: bool,
     flat: bool,
     steep: bool,
+    frozen: bool,
+    ocean: bool,
 }
 
-pub fn tag_cell(cell: &cell::CellProperties) -> BiomeTags {
+// Cells are wet where the ground is soaked. They are frozen where snow or ice
+// covers them. They are ocean where `ocean` says so, see `sea::find_oceans`.
+// With `seasonal` they are tagged by their water, snow and ice over the year
+// rather than what they have now, and are wet where the ground is soaked for
+// most of the year.
+pub fn tag_cell(cell: &cell::CellProperties, seasonal: bool, ocean: bool) -> BiomeTags {
     let cell::CellProperties {
         height: cell::Height(height),
-        water: cell::Water(water),
         gradient: cell::Gradient(gradient),
         ..
     } = cell;
+    let (water, wet, cover) = if seasonal {
+        (
+            cell.seasonal_water.0,
+            cell.seasonal_wetness.0 > 0.5,
+            cell.seasonal_cover.0,
+        )
+    } else {
+        (
+            cell.water.0,
+            soil::is_waterlogged(cell),
+            cell.snow.0 + cell.ice.0,
+        )
+    };
     BiomeTags {
-        wet: *water > 0.001,
-        water: *water > 0.05,
+        wet,
+        water: water > 0.05,
         high: *height > 0.3,
         flat: vec::len(gradient) < 0.08,
         steep: vec::len(gradient) > 0.1,
+        frozen: cover > 0.01,
+        ocean,
     }
 }
 
 pub fn classify_tags(tags: BiomeTags) -> BiomeType {
-    match (tags.wet, tags.water, tags.high, tags.flat, tags.steep) {
-        (_, true, _, _, _) => BiomeType::Lake,
-        (_, _, true, _, _) => BiomeType::Mountain,
-        (true, _, _, _, _) => BiomeType::Wetland,
-        (_, _, false, true, _) => BiomeType::Forest,
+    if tags.ocean {
+        return BiomeType::Ocean;
+    }
+    match (
+        tags.wet,
+        tags.water,
+        tags.high,
+        tags.flat,
+        tags.steep,
+        tags.frozen,
+    ) {
+        (_, true, _, _, _, _) => BiomeType::Lake,
+        (_, _, _, _, _, true) => BiomeType::Glacier,
+        (_, _, true, _, _, _) => BiomeType::Mountain,
+        (true, _, _, _, _, _) => BiomeType::Wetland,
+        (_, _, false, true, _, _) => BiomeType::Forest,
         _ => BiomeType::Nob,
     }
 }
@@ -65,41 +100,11 @@ pub fn classify_tags(tags: BiomeTags) -> BiomeType {
 pub fn tmp_colors(biome: BiomeType) -> (u8, u8, u8) {
     match biome {
         BiomeType::Mountain => (255, 0, 0),
+        BiomeType::Ocean => (0, 0, 128),
         BiomeType::Lake => (0, 0, 255),
         BiomeType::Wetland => (0, 100, 0),
         BiomeType::Forest => (0, 255, 0),
+        BiomeType::Glacier => (255, 255, 255),
         _ => (0, 0, 0),
     }
 }
-
-pub fn update_biomes(delta: f32, mut cells: Vec<cell::Cell>) -> Vec<cell::Cell> {
-    let evaporation_factor = 0.000005;
-    let mut water_vapor = 0.0;
-    let mut n_mountain = 0;
-    for mut cell in cells.iter_mut() {
-        match classify_tags(cell.biome_tags) {
-            BiomeType::Lake => {
-                cell.properties =
-                    cell.properties
-                        .alter_properties(-evaporation_factor * delta, 0.0, 0.0);
-                water_vapor += evaporation_factor * delta;
-            }
-            BiomeType::Mountain => {
-                n_mountain += 1;
-            }
-            _ => (),
-        };
-    }
-    let rain = water_vapor / n_mountain as f32;
-    for mut cell in cells.iter_mut() {
-        match classify_tags(cell.biome_tags) {
-            BiomeType::Mountain => {
-                cell.properties = cell.properties.alter_properties(rain, 0.0, 0.0);
-                water_vapor += evaporation_factor * delta;
-            }
-            _ => (),
-        };
-    }
-
-    cells
-}
@@ -0,0 +1,183 @@
+use cell;
+use std::str::FromStr;
+use vec;
+
+// What happens at one edge of the world. North is the first row, west the
+// first column.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Edge {
+    // Continues on the opposite edge, which should wrap as well
+    Wrap,
+    // A wall nothing flows through
+    Closed,
+    // Lets water and air flow out of the world, like the open sea
+    Open,
+    // Water is kept at the given depth just outside the edge and flows in
+    Inflow(f32),
+}
+
+impl FromStr for Edge {
+    type Err = String;
+
+    // Parses `wrap`, `closed`, `open` or `inflow:<depth>`
+    fn from_str(s: &str) -> Result<Edge, String> {
+        match s {
+            "wrap" => Ok(Edge::Wrap),
+            "closed" => Ok(Edge::Closed),
+            "open" => Ok(Edge::Open),
+            _ if s.starts_with("inflow:") => s["inflow:".len()..]
+                .parse()
+                .map(Edge::Inflow)
+                .map_err(|_| format!("invalid inflow depth: {}", s)),
+            _ => Err(format!("unknown edge: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Boundary {
+    pub north: Edge,
+    pub south: Edge,
+    pub east: Edge,
+    pub west: Edge,
+}
+
+// Where a neighbor of a cell ends up
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Neighbor {
+    Cell(u32, u32),
+    Outside(Edge),
+}
+
+impl Default for Boundary {
+    fn default() -> Boundary {
+        Boundary::torus()
+    }
+}
+
+impl Boundary {
+    pub fn uniform(edge: Edge) -> Boundary {
+        Boundary {
+            north: edge,
+            south: edge,
+            east: edge,
+            west: edge,
+        }
+    }
+
+    pub fn torus() -> Boundary {
+        Boundary::uniform(Edge::Wrap)
+    }
+
+    // Wraps east-west only, like a planet between two polar walls
+    pub fn cylinder() -> Boundary {
+        Boundary {
+            east: Edge::Wrap,
+            west: Edge::Wrap,
+            ..Boundary::uniform(Edge::Closed)
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<Boundary> {
+        match name {
+            "torus" => Some(Boundary::torus()),
+            "cylinder" => Some(Boundary::cylinder()),
+            "closed" => Some(Boundary::uniform(Edge::Closed)),
+            "open" => Some(Boundary::uniform(Edge::Open)),
+            _ => None,
+        }
+    }
+
+    // Wrapping only makes sense if the opposite edge wraps as well
+    pub fn is_valid(&self) -> bool {
+        (self.north == Edge::Wrap) == (self.south == Edge::Wrap)
+            && (self.east == Edge::Wrap) == (self.west == Edge::Wrap)
+    }
+
+    // Like `is_valid`, also checking the edges fit the grid. Wrapping
+    // north-south on a hex grid needs an even number of rows, so the rows keep
+    // alternating across the edge.
+    pub fn check(&self, stencil: cell::Stencil, height: u32) -> Result<(), String> {
+        if !self.is_valid() {
+            Err(String::from(
+                "wrapping edges must be paired with a wrapping opposite edge",
+            ))
+        } else if stencil == cell::Stencil::Hex
+            && self.north == Edge::Wrap
+            && !height.is_multiple_of(2)
+        {
+            Err(String::from(
+                "hex worlds wrapping north-south need an even height",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn resolve(&self, x: i32, y: i32, width: u32, height: u32) -> Neighbor {
+        let (width, height) = (width as i32, height as i32);
+        let x = match (x < 0, x >= width) {
+            (true, _) if self.west != Edge::Wrap => return Neighbor::Outside(self.west),
+            (_, true) if self.east != Edge::Wrap => return Neighbor::Outside(self.east),
+            (false, false) => x,
+            _ => x.rem_euclid(width),
+        };
+        let y = match (y < 0, y >= height) {
+            (true, _) if self.north != Edge::Wrap => return Neighbor::Outside(self.north),
+            (_, true) if self.south != Edge::Wrap => return Neighbor::Outside(self.south),
+            (false, false) => y,
+            _ => y.rem_euclid(height),
+        };
+        Neighbor::Cell(x as u32, y as u32)
+    }
+}
+
+fn reflect(v: &vec::Vec2f, normal: &vec::Vec2f) -> vec::Vec2f {
+    vec::diff(v, &vec::mul(2.0 * vec::dot(v, normal), normal))
+}
+
+// Drops the part of `v` pointing against `normal`
+fn outward_only(v: &vec::Vec2f, normal: &vec::Vec2f) -> vec::Vec2f {
+    let along = vec::dot(v, normal);
+    if along < 0.0 {
+        vec::diff(v, &vec::mul(along, normal))
+    } else {
+        *v
+    }
+}
+
+// Properties of the made up cell just outside an edge, `me` being the cell
+// inside and `outward` the direction from it to the outside cell. Wind and
+// water only leave through open and inflow edges, into still, dry air at the
+// ambient pressure, so nothing but the inflow itself enters the world there.
+pub fn ghost_cell(
+    edge: Edge,
+    me: &cell::NeighborProperties,
+    outward: &vec::Vec2f,
+) -> cell::NeighborProperties {
+    let normal = vec::normalize(outward);
+    match edge {
+        Edge::Wrap | Edge::Closed => cell::NeighborProperties {
+            wind: cell::Wind(reflect(&me.wind.0, &normal)),
+            water_flow: cell::WaterFlow(reflect(&me.water_flow.0, &normal)),
+            ..*me
+        },
+        Edge::Open => cell::NeighborProperties {
+            air_pressure: cell::AirPressure(cell::AMBIENT_AIR_PRESSURE),
+            wind: cell::Wind(outward_only(&me.wind.0, &normal)),
+            water: cell::Water(0.0),
+            water_flow: cell::WaterFlow(outward_only(&me.water_flow.0, &normal)),
+            humidity: cell::Humidity(0.0),
+            ..*me
+        },
+        Edge::Inflow(depth) => {
+            // Flows in until the edge cell is as deep as the inflow
+            let inflow = (depth - me.water.0).max(0.0);
+            cell::NeighborProperties {
+                water: cell::Water(depth),
+                water_flow: cell::WaterFlow(vec::mul(-inflow, &normal)),
+                ..ghost_cell(Edge::Open, me, outward)
+            }
+        }
+    }
+}
@@ -1,8 +1,11 @@
 use biome;
-#[allow(dead_code)]
-use utils;
+use climate;
+use erosion;
+use ice;
+use soil;
+use std::str::FromStr;
 use vec;
-use vec::Vector;
+use water;
 use world;
 
 #[derive(Copy, Clone)]
@@ -29,23 +32,288 @@ pub struct Heat(pub f32);
 #[derive(Copy, Clone)]
 pub struct Resources(pub f32);
 
-pub struct Neighborhood {
-    pub up: CellProperties,
-    pub down: CellProperties,
-    pub left: CellProperties,
-    pub right: CellProperties,
-    pub me: CellProperties,
+#[derive(Copy, Clone)]
+pub struct Sediment(pub f32);
+
+#[derive(Copy, Clone)]
+pub struct Humidity(pub f32);
+
+#[derive(Copy, Clone)]
+pub struct SeasonalWater(pub f32);
+
+#[derive(Copy, Clone)]
+pub struct SeasonalCover(pub f32);
+
+#[derive(Copy, Clone)]
+pub struct SeasonalWetness(pub f32);
+
+#[derive(Copy, Clone)]
+pub struct SoilMoisture(pub f32);
+
+#[derive(Copy, Clone)]
+pub struct Groundwater(pub f32);
+
+#[derive(Copy, Clone)]
+pub struct Snow(pub f32);
+
+#[derive(Copy, Clone)]
+pub struct Ice(pub f32);
+
+// Which neighbors a cell exchanges air, water and heat with
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Stencil {
+    // The 4 neighbors sharing an edge
+    VonNeumann,
+    // All 8 surrounding neighbors
+    Moore,
+    // Hexagonal cells with 6 neighbors. Odd rows are shifted half a cell
+    // east and rows are sqrt(3) / 2 apart, so all neighbors are 1 apart.
+    Hex,
+}
+
+const HEX_ROW_SPACING: f32 = 0.866_025_4;
+
+impl Stencil {
+    // Grid offsets of the neighbors of a cell in row `y`, with their weights.
+    // The weights are picked so that sum(weight * offset * offset^T) is the same
+    // for every stencil, which keeps the rates of the update functions
+    // independent of the stencil, and for Moore gives the isotropic 9 point
+    // laplacian.
+    pub fn links(self, y: u32) -> &'static [(i32, i32, f32)] {
+        match self {
+            Stencil::VonNeumann => &[(0, -1, 1.0), (0, 1, 1.0), (-1, 0, 1.0), (1, 0, 1.0)],
+            Stencil::Moore => &[
+                (0, -1, 2.0 / 3.0),
+                (0, 1, 2.0 / 3.0),
+                (-1, 0, 2.0 / 3.0),
+                (1, 0, 2.0 / 3.0),
+                (-1, -1, 1.0 / 6.0),
+                (1, -1, 1.0 / 6.0),
+                (-1, 1, 1.0 / 6.0),
+                (1, 1, 1.0 / 6.0),
+            ],
+            Stencil::Hex if y.is_multiple_of(2) => &[
+                (-1, -1, 2.0 / 3.0),
+                (0, -1, 2.0 / 3.0),
+                (-1, 1, 2.0 / 3.0),
+                (0, 1, 2.0 / 3.0),
+                (-1, 0, 2.0 / 3.0),
+                (1, 0, 2.0 / 3.0),
+            ],
+            Stencil::Hex => &[
+                (0, -1, 2.0 / 3.0),
+                (1, -1, 2.0 / 3.0),
+                (0, 1, 2.0 / 3.0),
+                (1, 1, 2.0 / 3.0),
+                (-1, 0, 2.0 / 3.0),
+                (1, 0, 2.0 / 3.0),
+            ],
+        }
+    }
+
+    // Position of the center of a cell, in the same units as the grid
+    pub fn position(self, x: i32, y: i32) -> (f32, f32) {
+        match self {
+            Stencil::Hex => (
+                x as f32 + 0.5 * y.rem_euclid(2) as f32,
+                y as f32 * HEX_ROW_SPACING,
+            ),
+            _ => (x as f32, y as f32),
+        }
+    }
+
+    // Vector between the centers of a cell in row `y` and its neighbor at the
+    // grid offset (dx, dy). Worked out from the offset alone rather than from
+    // the two positions, so that it is exactly the negated offset back.
+    pub fn offset(self, y: u32, dx: i32, dy: i32) -> vec::Vec2f {
+        match self {
+            Stencil::Hex => {
+                let shift = (y as i32 + dy).rem_euclid(2) - (y as i32).rem_euclid(2);
+                vec::Vec2f::new(dx as f32 + 0.5 * shift as f32, dy as f32 * HEX_ROW_SPACING)
+            }
+            _ => vec::Vec2f::new(dx as f32, dy as f32),
+        }
+    }
+
+    // Distance between rows
+    pub fn row_spacing(self) -> f32 {
+        match self {
+            Stencil::Hex => HEX_ROW_SPACING,
+            _ => 1.0,
+        }
+    }
+
+    // Sum of the weights of the links of a cell
+    pub fn weight_sum(self) -> f32 {
+        self.links(0).iter().map(|&(_, _, weight)| weight).sum()
+    }
+
+    // Sum of the weighted lengths of the links of a cell
+    pub fn reach(self) -> f32 {
+        self.links(0)
+            .iter()
+            .map(|&(dx, dy, weight)| weight * vec::len(&self.offset(0, dx, dy)))
+            .sum()
+    }
+
+    // Index of the link of the neighbor at the end of link `k` of a cell in
+    // row `y` that leads back to the cell
+    pub fn reverse(self, y: u32, k: usize) -> usize {
+        let (dx, dy, _) = self.links(y)[k];
+        let row = (y as i32 + dy).rem_euclid(2) as u32;
+        self.links(row)
+            .iter()
+            .position(|&(x, y, _)| x == -dx && y == -dy)
+            .unwrap_or(k)
+    }
+}
+
+impl FromStr for Stencil {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Stencil, String> {
+        match s {
+            "von-neumann" => Ok(Stencil::VonNeumann),
+            "moore" => Ok(Stencil::Moore),
+            "hex" => Ok(Stencil::Hex),
+            _ => Err(format!("unknown stencil: {}", s)),
+        }
+    }
 }
 
-impl Neighborhood {
+// Most links any stencil has
+pub const MAX_LINKS: usize = 8;
+
+#[derive(Copy, Clone)]
+pub struct Link {
+    pub offset: vec::Vec2f,
+    pub weight: f32,
+    pub properties: NeighborProperties,
+}
+
+// The properties cells exchange with their neighbors, the only ones copied out
+// of the fields for every link
+#[derive(Copy, Clone)]
+pub struct NeighborProperties {
+    pub height: Height,
+    pub air_pressure: AirPressure,
+    pub wind: Wind,
+    pub water: Water,
+    pub water_flow: WaterFlow,
+    pub heat: Heat,
+    pub humidity: Humidity,
+    pub groundwater: Groundwater,
+    pub ice: Ice,
+}
+
+impl NeighborProperties {
+    pub fn total_height(&self) -> f32 {
+        self.height.0 + self.water.0
+    }
+}
+
+impl Link {
+    pub fn direction(&self) -> vec::Vec2f {
+        vec::normalize(&self.offset)
+    }
+}
+
+pub struct Neighborhood<'a> {
+    pub me: NeighborProperties,
+    pub links: &'a [Link],
+    // Prevailing wind, Coriolis parameter and sunlight at the latitude of the
+    // cell, see `climate`
+    pub prevailing_wind: vec::Vec2f,
+    pub coriolis: f32,
+    pub insolation: f32,
+}
+
+// What the cells of a world follow when they step
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Rules {
+    pub water_model: water::WaterModel,
+    pub talus: erosion::Talus,
+    pub calendar: climate::Calendar,
+}
+
+impl<'a> Neighborhood<'a> {
     pub fn get_gradient(&self) -> vec::Vec2f {
-        vec::Vec2f::new(
-            (self.right.height.0 - self.left.height.0) / 2.0,
-            (self.up.height.0 - self.down.height.0) / 2.0,
-        )
+        let (mut x, mut y, mut norm) = (0.0, 0.0, 0.0);
+        for link in self.links.iter() {
+            let (dx, dy) = link.offset.xy();
+            let dh = link.properties.height.0 - self.me.height.0;
+            x += link.weight * dx * dh;
+            y += link.weight * dy * dh;
+            norm += link.weight * (dx * dx + dy * dy);
+        }
+        vec::Vec2f::new(2.0 * x / norm, 2.0 * y / norm)
+    }
+
+    // What flows into the cell from a vector field on the neighbors
+    pub fn inflow<F>(&self, field: F) -> f32
+    where
+        F: Fn(&NeighborProperties) -> vec::Vec2f,
+    {
+        self.links.iter().fold(0.0, |acc, link| {
+            acc + link.weight * -vec::dot(&field(&link.properties), &link.offset)
+        })
+    }
+
+    // Net amount moving into the cell over its links, `flow(from, to, offset)`
+    // being what moves between two cells `offset` apart per unit of time and
+    // link weight. What a cell loses over a link is exactly what the cell at
+    // the other end gains.
+    pub fn exchange<F>(&self, flow: F) -> f32
+    where
+        F: Fn(&NeighborProperties, &NeighborProperties, &vec::Vec2f) -> f32,
+    {
+        self.links.iter().fold(0.0, |acc, link| {
+            let back = vec::mul(-1.0, &link.offset);
+            acc + link.weight
+                * (flow(&link.properties, &self.me, &back)
+                    - flow(&self.me, &link.properties, &link.offset))
+        })
+    }
+
+    // Net amount of a value the wind carries into the cell, each link taking
+    // it from the cell upwind along it
+    pub fn advect<F>(&self, value: F) -> f32
+    where
+        F: Fn(&NeighborProperties) -> f32,
+    {
+        self.exchange(|from, to, offset| {
+            let wind = vec::add(&from.wind.0, &to.wind.0);
+            (0.25 * vec::dot(&wind, offset)).max(0.0) * value(from)
+        })
+    }
+
+    // Sum of a value per link, pushing away from the neighbors with positive values
+    pub fn push<F>(&self, value: F) -> vec::Vec2f
+    where
+        F: Fn(&Link) -> f32,
+    {
+        let (x, y) = self.links.iter().fold((0.0, 0.0), |(x, y), link| {
+            let v = value(link);
+            let (dx, dy) = link.offset.xy();
+            (x + -link.weight * v * dx, y + -link.weight * v * dy)
+        });
+        vec::Vec2f::new(x, y)
+    }
+
+    // Weighted sum of the differences to the neighbors
+    pub fn laplacian<F>(&self, value: F) -> f32
+    where
+        F: Fn(&NeighborProperties) -> f32,
+    {
+        let me = value(&self.me);
+        self.links.iter().fold(0.0, |acc, link| {
+            acc + link.weight * (value(&link.properties) - me)
+        })
     }
 }
 
+// A copy of everything stored about one cell
+#[derive(Copy, Clone)]
 pub struct Cell {
     pub properties: CellProperties,
     pub biome_tags: biome::BiomeTags,
@@ -53,26 +321,334 @@ pub struct Cell {
     pub y: u32,
 }
 
-impl Cell {
-    pub fn new(description: &world::WorldDescription, x: u32, y: u32) -> Cell {
-        let properties = CellProperties::new(description, x, y);
-        Cell {
-            properties: properties,
-            biome_tags: biome::tag_cell(&properties),
-            x: x,
-            y: y,
+// The properties of all cells of a world, each in its own array indexed by
+// y * width + x
+#[derive(Clone, Default)]
+pub struct Fields {
+    pub height: Vec<f32>,
+    pub gradient: Vec<vec::Vec2f>,
+    pub air_pressure: Vec<f32>,
+    pub wind: Vec<vec::Vec2f>,
+    pub water: Vec<f32>,
+    pub water_flow: Vec<vec::Vec2f>,
+    pub heat: Vec<f32>,
+    pub resources: Vec<f32>,
+    pub sediment: Vec<f32>,
+    pub humidity: Vec<f32>,
+    pub seasonal_water: Vec<f32>,
+    pub seasonal_cover: Vec<f32>,
+    pub seasonal_wetness: Vec<f32>,
+    pub soil_moisture: Vec<f32>,
+    pub groundwater: Vec<f32>,
+    pub snow: Vec<f32>,
+    pub ice: Vec<f32>,
+    pub biome_tags: Vec<biome::BiomeTags>,
+    // Water flowing out through each link of a cell, for the pipe water model
+    pub outflow: Vec<[f32; MAX_LINKS]>,
+    // Whether each cell is part of the ocean, see `sea::find_oceans`
+    pub ocean: Vec<bool>,
+}
+
+impl Fields {
+    pub fn new(description: &world::WorldDescription) -> Fields {
+        let (width, height) = description.heightmap.size();
+        let cells: Vec<CellProperties> = (0..width * height)
+            .map(|i| CellProperties::new(description, i % width, i / width))
+            .collect();
+        Fields {
+            height: cells.iter().map(|cell| cell.height.0).collect(),
+            gradient: cells.iter().map(|cell| cell.gradient.0).collect(),
+            air_pressure: cells.iter().map(|cell| cell.air_pressure.0).collect(),
+            wind: cells.iter().map(|cell| cell.wind.0).collect(),
+            water: cells.iter().map(|cell| cell.water.0).collect(),
+            water_flow: cells.iter().map(|cell| cell.water_flow.0).collect(),
+            heat: cells.iter().map(|cell| cell.heat.0).collect(),
+            resources: cells.iter().map(|cell| cell.resources.0).collect(),
+            sediment: cells.iter().map(|cell| cell.sediment.0).collect(),
+            humidity: cells.iter().map(|cell| cell.humidity.0).collect(),
+            seasonal_water: cells.iter().map(|cell| cell.seasonal_water.0).collect(),
+            seasonal_cover: cells.iter().map(|cell| cell.seasonal_cover.0).collect(),
+            seasonal_wetness: cells.iter().map(|cell| cell.seasonal_wetness.0).collect(),
+            soil_moisture: cells.iter().map(|cell| cell.soil_moisture.0).collect(),
+            groundwater: cells.iter().map(|cell| cell.groundwater.0).collect(),
+            snow: cells.iter().map(|cell| cell.snow.0).collect(),
+            ice: cells.iter().map(|cell| cell.ice.0).collect(),
+            biome_tags: cells
+                .iter()
+                .map(|cell| biome::tag_cell(cell, false, false))
+                .collect(),
+            outflow: vec![[0.0; MAX_LINKS]; cells.len()],
+            ocean: vec![false; cells.len()],
         }
     }
 
-    pub fn update(&self, delta: f32, neighborhood: &Neighborhood) -> Cell {
-        let new_props = CellProperties::step(&self.properties, delta, neighborhood);
-        Cell {
-            properties: new_props,
-            biome_tags: biome::tag_cell(&new_props),
-            x: self.x,
-            y: self.y,
+    pub fn len(&self) -> usize {
+        self.height.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.height.is_empty()
+    }
+
+    // The scalar fields by name
+    pub fn scalars(&self) -> [(&'static str, &[f32]); 14] {
+        [
+            ("height", &self.height),
+            ("air_pressure", &self.air_pressure),
+            ("water", &self.water),
+            ("heat", &self.heat),
+            ("resources", &self.resources),
+            ("sediment", &self.sediment),
+            ("humidity", &self.humidity),
+            ("seasonal_water", &self.seasonal_water),
+            ("seasonal_cover", &self.seasonal_cover),
+            ("seasonal_wetness", &self.seasonal_wetness),
+            ("soil_moisture", &self.soil_moisture),
+            ("groundwater", &self.groundwater),
+            ("snow", &self.snow),
+            ("ice", &self.ice),
+        ]
+    }
+
+    pub fn scalars_mut(&mut self) -> [(&'static str, &mut [f32]); 14] {
+        [
+            ("height", &mut self.height),
+            ("air_pressure", &mut self.air_pressure),
+            ("water", &mut self.water),
+            ("heat", &mut self.heat),
+            ("resources", &mut self.resources),
+            ("sediment", &mut self.sediment),
+            ("humidity", &mut self.humidity),
+            ("seasonal_water", &mut self.seasonal_water),
+            ("seasonal_cover", &mut self.seasonal_cover),
+            ("seasonal_wetness", &mut self.seasonal_wetness),
+            ("soil_moisture", &mut self.soil_moisture),
+            ("groundwater", &mut self.groundwater),
+            ("snow", &mut self.snow),
+            ("ice", &mut self.ice),
+        ]
+    }
+
+    // The vector fields by name
+    pub fn vectors(&self) -> [(&'static str, &[vec::Vec2f]); 3] {
+        [
+            ("gradient", &self.gradient),
+            ("wind", &self.wind),
+            ("water_flow", &self.water_flow),
+        ]
+    }
+
+    pub fn vectors_mut(&mut self) -> [(&'static str, &mut [vec::Vec2f]); 3] {
+        [
+            ("gradient", &mut self.gradient),
+            ("wind", &mut self.wind),
+            ("water_flow", &mut self.water_flow),
+        ]
+    }
+
+    pub fn get(&self, i: usize) -> CellProperties {
+        CellProperties {
+            height: Height(self.height[i]),
+            gradient: Gradient(self.gradient[i]),
+            air_pressure: AirPressure(self.air_pressure[i]),
+            wind: Wind(self.wind[i]),
+            water: Water(self.water[i]),
+            water_flow: WaterFlow(self.water_flow[i]),
+            heat: Heat(self.heat[i]),
+            resources: Resources(self.resources[i]),
+            sediment: Sediment(self.sediment[i]),
+            humidity: Humidity(self.humidity[i]),
+            seasonal_water: SeasonalWater(self.seasonal_water[i]),
+            seasonal_cover: SeasonalCover(self.seasonal_cover[i]),
+            seasonal_wetness: SeasonalWetness(self.seasonal_wetness[i]),
+            soil_moisture: SoilMoisture(self.soil_moisture[i]),
+            groundwater: Groundwater(self.groundwater[i]),
+            snow: Snow(self.snow[i]),
+            ice: Ice(self.ice[i]),
         }
     }
+
+    pub fn get_neighbor(&self, i: usize) -> NeighborProperties {
+        NeighborProperties {
+            height: Height(self.height[i]),
+            air_pressure: AirPressure(self.air_pressure[i]),
+            wind: Wind(self.wind[i]),
+            water: Water(self.water[i]),
+            water_flow: WaterFlow(self.water_flow[i]),
+            heat: Heat(self.heat[i]),
+            humidity: Humidity(self.humidity[i]),
+            groundwater: Groundwater(self.groundwater[i]),
+            ice: Ice(self.ice[i]),
+        }
+    }
+
+    // Stores the properties of a cell, leaving its biome tags as they are
+    pub fn set(&mut self, i: usize, properties: &CellProperties) {
+        self.height[i] = properties.height.0;
+        self.gradient[i] = properties.gradient.0;
+        self.air_pressure[i] = properties.air_pressure.0;
+        self.wind[i] = properties.wind.0;
+        self.water[i] = properties.water.0;
+        self.water_flow[i] = properties.water_flow.0;
+        self.heat[i] = properties.heat.0;
+        self.resources[i] = properties.resources.0;
+        self.sediment[i] = properties.sediment.0;
+        self.humidity[i] = properties.humidity.0;
+        self.seasonal_water[i] = properties.seasonal_water.0;
+        self.seasonal_cover[i] = properties.seasonal_cover.0;
+        self.seasonal_wetness[i] = properties.seasonal_wetness.0;
+        self.soil_moisture[i] = properties.soil_moisture.0;
+        self.groundwater[i] = properties.groundwater.0;
+        self.snow[i] = properties.snow.0;
+        self.ice[i] = properties.ice.0;
+    }
+
+    pub fn alter(&mut self, i: usize, d_water: f32, d_air_pressure: f32, d_height: f32) {
+        let altered = self
+            .get(i)
+            .alter_properties(d_water, d_air_pressure, d_height);
+        self.set(i, &altered);
+    }
+
+    pub fn as_mut(&mut self) -> FieldsMut<'_> {
+        FieldsMut {
+            height: &mut self.height,
+            gradient: &mut self.gradient,
+            air_pressure: &mut self.air_pressure,
+            wind: &mut self.wind,
+            water: &mut self.water,
+            water_flow: &mut self.water_flow,
+            heat: &mut self.heat,
+            resources: &mut self.resources,
+            sediment: &mut self.sediment,
+            humidity: &mut self.humidity,
+            seasonal_water: &mut self.seasonal_water,
+            seasonal_cover: &mut self.seasonal_cover,
+            seasonal_wetness: &mut self.seasonal_wetness,
+            soil_moisture: &mut self.soil_moisture,
+            groundwater: &mut self.groundwater,
+            snow: &mut self.snow,
+            ice: &mut self.ice,
+            biome_tags: &mut self.biome_tags,
+        }
+    }
+}
+
+// A mutable view of a run of cells of `Fields`, which can be split so that
+// separate threads write separate cells
+pub struct FieldsMut<'a> {
+    pub height: &'a mut [f32],
+    pub gradient: &'a mut [vec::Vec2f],
+    pub air_pressure: &'a mut [f32],
+    pub wind: &'a mut [vec::Vec2f],
+    pub water: &'a mut [f32],
+    pub water_flow: &'a mut [vec::Vec2f],
+    pub heat: &'a mut [f32],
+    pub resources: &'a mut [f32],
+    pub sediment: &'a mut [f32],
+    pub humidity: &'a mut [f32],
+    pub seasonal_water: &'a mut [f32],
+    pub seasonal_cover: &'a mut [f32],
+    pub seasonal_wetness: &'a mut [f32],
+    pub soil_moisture: &'a mut [f32],
+    pub groundwater: &'a mut [f32],
+    pub snow: &'a mut [f32],
+    pub ice: &'a mut [f32],
+    pub biome_tags: &'a mut [biome::BiomeTags],
+}
+
+impl<'a> FieldsMut<'a> {
+    pub fn len(&self) -> usize {
+        self.height.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.height.is_empty()
+    }
+
+    // Same as `Fields::set`, with `i` relative to the start of the view
+    pub fn set(&mut self, i: usize, properties: &CellProperties) {
+        self.height[i] = properties.height.0;
+        self.gradient[i] = properties.gradient.0;
+        self.air_pressure[i] = properties.air_pressure.0;
+        self.wind[i] = properties.wind.0;
+        self.water[i] = properties.water.0;
+        self.water_flow[i] = properties.water_flow.0;
+        self.heat[i] = properties.heat.0;
+        self.resources[i] = properties.resources.0;
+        self.sediment[i] = properties.sediment.0;
+        self.humidity[i] = properties.humidity.0;
+        self.seasonal_water[i] = properties.seasonal_water.0;
+        self.seasonal_cover[i] = properties.seasonal_cover.0;
+        self.seasonal_wetness[i] = properties.seasonal_wetness.0;
+        self.soil_moisture[i] = properties.soil_moisture.0;
+        self.groundwater[i] = properties.groundwater.0;
+        self.snow[i] = properties.snow.0;
+        self.ice[i] = properties.ice.0;
+    }
+
+    pub fn split_at(self, mid: usize) -> (FieldsMut<'a>, FieldsMut<'a>) {
+        let (height, height_rest) = self.height.split_at_mut(mid);
+        let (gradient, gradient_rest) = self.gradient.split_at_mut(mid);
+        let (air_pressure, air_pressure_rest) = self.air_pressure.split_at_mut(mid);
+        let (wind, wind_rest) = self.wind.split_at_mut(mid);
+        let (water, water_rest) = self.water.split_at_mut(mid);
+        let (water_flow, water_flow_rest) = self.water_flow.split_at_mut(mid);
+        let (heat, heat_rest) = self.heat.split_at_mut(mid);
+        let (resources, resources_rest) = self.resources.split_at_mut(mid);
+        let (sediment, sediment_rest) = self.sediment.split_at_mut(mid);
+        let (humidity, humidity_rest) = self.humidity.split_at_mut(mid);
+        let (seasonal_water, seasonal_water_rest) = self.seasonal_water.split_at_mut(mid);
+        let (seasonal_cover, seasonal_cover_rest) = self.seasonal_cover.split_at_mut(mid);
+        let (seasonal_wetness, seasonal_wetness_rest) = self.seasonal_wetness.split_at_mut(mid);
+        let (soil_moisture, soil_moisture_rest) = self.soil_moisture.split_at_mut(mid);
+        let (groundwater, groundwater_rest) = self.groundwater.split_at_mut(mid);
+        let (snow, snow_rest) = self.snow.split_at_mut(mid);
+        let (ice, ice_rest) = self.ice.split_at_mut(mid);
+        let (biome_tags, biome_tags_rest) = self.biome_tags.split_at_mut(mid);
+        (
+            FieldsMut {
+                height,
+                gradient,
+                air_pressure,
+                wind,
+                water,
+                water_flow,
+                heat,
+                resources,
+                sediment,
+                humidity,
+                seasonal_water,
+                seasonal_cover,
+                seasonal_wetness,
+                soil_moisture,
+                groundwater,
+                snow,
+                ice,
+                biome_tags,
+            },
+            FieldsMut {
+                height: height_rest,
+                gradient: gradient_rest,
+                air_pressure: air_pressure_rest,
+                wind: wind_rest,
+                water: water_rest,
+                water_flow: water_flow_rest,
+                heat: heat_rest,
+                resources: resources_rest,
+                sediment: sediment_rest,
+                humidity: humidity_rest,
+                seasonal_water: seasonal_water_rest,
+                seasonal_cover: seasonal_cover_rest,
+                seasonal_wetness: seasonal_wetness_rest,
+                soil_moisture: soil_moisture_rest,
+                groundwater: groundwater_rest,
+                snow: snow_rest,
+                ice: ice_rest,
+                biome_tags: biome_tags_rest,
+            },
+        )
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -85,21 +661,46 @@ pub struct CellProperties {
     pub water_flow: WaterFlow,
     pub heat: Heat,
     pub resources: Resources,
+    pub sediment: Sediment,
+    pub humidity: Humidity,
+    pub seasonal_water: SeasonalWater,
+    pub seasonal_cover: SeasonalCover,
+    pub seasonal_wetness: SeasonalWetness,
+    pub soil_moisture: SoilMoisture,
+    pub groundwater: Groundwater,
+    pub snow: Snow,
+    pub ice: Ice,
 }
 
 impl CellProperties {
     fn new(description: &world::WorldDescription, x: u32, y: u32) -> CellProperties {
         let waterlevel =
-            (description.waterlevel.get(x, y) - 0.2 - description.heightmap.get(x, y)).max(0.0);
-        CellProperties {
+            (description.waterlevel.get(x, y) - description.heightmap.get(x, y)).max(0.0);
+        let cell = CellProperties {
             height: Height(description.heightmap.get(x, y)),
             gradient: Gradient(vec::Vec2f::new(0.0, 0.0)),
-            air_pressure: AirPressure(1.0),
+            air_pressure: AirPressure(AMBIENT_AIR_PRESSURE),
             wind: Wind(vec::Vec2f::new(0.0, 0.0)),
             water: Water(waterlevel),
             water_flow: WaterFlow(vec::Vec2f::new(0.0, 0.0)),
-            heat: Heat(description.heightmap.get(x, y)),
-            resources: Resources(0.0),
+            heat: Heat(
+                description.heatmap.get(x, y)
+                    - climate::LAPSE_RATE * climate::altitude(description.heightmap.get(x, y)),
+            ),
+            resources: Resources(soil::INITIAL_RESOURCES),
+            sediment: Sediment(0.0),
+            humidity: Humidity(0.0),
+            seasonal_water: SeasonalWater(waterlevel),
+            seasonal_cover: SeasonalCover(0.0),
+            seasonal_wetness: SeasonalWetness(0.0),
+            soil_moisture: SoilMoisture(0.5 * soil::SOIL_CAPACITY),
+            groundwater: Groundwater(0.5 * soil::AQUIFER_CAPACITY),
+            snow: Snow(0.0),
+            ice: Ice(0.0),
+        };
+        CellProperties {
+            seasonal_wetness: SeasonalWetness(waterlogging(&cell)),
+            ..cell
         }
     }
 
@@ -107,16 +708,45 @@ impl CellProperties {
         self.height.0 + self.water.0
     }
 
-    fn step(current: &CellProperties, delta: f32, neighborhood: &Neighborhood) -> CellProperties {
+    // With the pipe water model the water is left as it is, it is moved for
+    // the whole world at once by `water::outflows` and `water::transport`
+    pub fn step(
+        current: &CellProperties,
+        delta: f32,
+        neighborhood: &Neighborhood,
+        rules: &Rules,
+    ) -> CellProperties {
+        let (water, water_flow) = match rules.water_model {
+            water::WaterModel::Flow => (
+                update_water(delta, neighborhood),
+                update_water_flow(delta, neighborhood),
+            ),
+            water::WaterModel::Pipes => (current.water, current.water_flow),
+        };
+        let (height, sediment) = scour(
+            delta,
+            neighborhood,
+            update_height(delta, neighborhood, &rules.talus),
+            current.sediment,
+        );
         CellProperties {
-            height: current.height,
+            height,
             gradient: Gradient(neighborhood.get_gradient()),
             air_pressure: update_air_pressure(delta, neighborhood),
             wind: update_wind(delta, neighborhood),
-            water: update_water(delta, neighborhood),
-            water_flow: update_water_flow(delta, neighborhood),
+            water,
+            water_flow,
             heat: update_heat(delta, neighborhood),
-            resources: update_resources(delta, neighborhood),
+            resources: update_resources(delta, current),
+            sediment,
+            humidity: update_humidity(delta, neighborhood),
+            seasonal_water: update_seasonal_water(delta, current, &water, &rules.calendar),
+            seasonal_cover: update_seasonal_cover(delta, current, &rules.calendar),
+            seasonal_wetness: update_seasonal_wetness(delta, current, &rules.calendar),
+            soil_moisture: current.soil_moisture,
+            groundwater: update_groundwater(delta, neighborhood),
+            snow: current.snow,
+            ice: update_ice(delta, neighborhood),
         }
     }
 
@@ -129,80 +759,130 @@ impl CellProperties {
         CellProperties {
             water: Water((self.water.0 + d_water).max(0.0)),
             air_pressure: AirPressure((self.air_pressure.0 + d_air_pressure).max(0.0)),
-            height: Height((self.height.0 + d_height).min(1.0).max(-1.0)),
+            height: Height((self.height.0 + d_height).clamp(-1.0, 1.0)),
             ..*self
         }
     }
 }
 
-fn update_air_pressure(delta: f32, neighborhood: &Neighborhood) -> AirPressure {
-    let air_propagation_factor = 0.5;
+// Air pressure cells start at, and the air outside open edges keeps
+pub const AMBIENT_AIR_PRESSURE: f32 = 1.0;
+
+const AIR_PROPAGATION_FACTOR: f32 = 0.5;
+const WIND_PROPAGATION_FACTOR: f32 = 0.25;
+const CIRCULATION_FACTOR: f32 = 0.15;
+const WATER_PROPAGATION_FACTOR: f32 = 0.5;
+const WATER_FLOW_PROPAGATION_FACTOR: f32 = 0.9;
+const HEAT_PROPAGATION_FACTOR: f32 = 1.0;
+const CONVECTION_FACTOR: f32 = 2.0;
+
+// Largest time step the update functions stay stable for, given the fastest
+// wind and water flow in the world. Heat diffusion is stable while the weight
+// a cell gives its neighbors stays below its own, the wind and water flow
+// relaxations while they don't overshoot their targets, and air and water
+// while nothing is carried further than one cell per step (the CFL condition).
+pub fn stable_delta(stencil: Stencil, max_wind: f32, max_water_flow: f32) -> f32 {
+    let reach = stencil.reach();
+    [
+        1.0 / (HEAT_PROPAGATION_FACTOR * stencil.weight_sum()),
+        1.0 / (WIND_PROPAGATION_FACTOR + CIRCULATION_FACTOR),
+        1.0 / WATER_FLOW_PROPAGATION_FACTOR,
+        1.0 / (AIR_PROPAGATION_FACTOR * reach * max_wind),
+        1.0 / (WATER_PROPAGATION_FACTOR * reach * max_water_flow),
+    ]
+    .iter()
+    .fold(f32::INFINITY, |acc, &limit| acc.min(limit))
+}
+
+// Ground slumps down slopes steeper than the talus angle. Each link moves half
+// of the height difference above the angle per unit of rate and time. The
+// slope along a link is one of the differences `get_gradient` is built from.
+fn update_height(delta: f32, neighborhood: &Neighborhood, talus: &erosion::Talus) -> Height {
+    let me = neighborhood.me.height.0;
+    if talus.is_none() {
+        return Height(me);
+    }
+    let max_slope = talus.max_slope();
+    let slump = neighborhood.exchange(|from, to, offset| {
+        (from.height.0 - to.height.0 - max_slope * vec::len(offset)).max(0.0)
+    });
+    Height((me + 0.5 * delta * talus.rate * slump).clamp(-1.0, 1.0))
+}
 
-    let diff_down = neighborhood.down.wind.0.get(1);
-    let diff_up = -neighborhood.up.wind.0.get(1);
-    let diff_left = neighborhood.left.wind.0.get(0);
-    let diff_right = -neighborhood.right.wind.0.get(0);
+// Ice creeping out of a cell scrapes the ground under it off into sediment
+fn scour(
+    delta: f32,
+    neighborhood: &Neighborhood,
+    height: Height,
+    sediment: Sediment,
+) -> (Height, Sediment) {
+    let creep = neighborhood.links.iter().fold(0.0, |acc, link| {
+        acc + link.weight * ice::flow(&neighborhood.me, &link.properties)
+    });
+    let scoured = height.0 - (delta * ice::SCOURING * creep).min(height.0 + 1.0);
+    (Height(scoured), Sediment(sediment.0 + (height.0 - scoured)))
+}
 
+// Air comes in with the wind, and rises out of cells warmer than their
+// neighbors to sink into the cooler ones around them
+fn update_air_pressure(delta: f32, neighborhood: &Neighborhood) -> AirPressure {
     AirPressure(
         neighborhood.me.air_pressure.0
-            + delta * air_propagation_factor * (diff_down + diff_up + diff_left + diff_right),
+            + delta
+                * (AIR_PROPAGATION_FACTOR * neighborhood.inflow(|close| close.wind.0)
+                    + CONVECTION_FACTOR * neighborhood.laplacian(climate::potential_temperature)),
     )
 }
 
-fn air_pressure_diff(me: CellProperties, close: CellProperties) -> f32 {
+// Pressure the wind feels: the weight of the air and what's under it, less
+// the lift of warm air
+fn pressure(cell: &NeighborProperties) -> f32 {
     let gravity_factor = 0.5;
+    cell.total_height() * gravity_factor + cell.air_pressure.0
+        - climate::BUOYANCY * climate::potential_temperature(cell)
+}
 
-    if close.total_height() * gravity_factor + close.air_pressure.0
-        > me.total_height() * gravity_factor + me.air_pressure.0
-    {
-        close.air_pressure.0.min(
-            (close.total_height() * gravity_factor + close.air_pressure.0
-                - me.total_height() * gravity_factor
-                - me.air_pressure.0)
-                .max(0.0),
-        )
+fn air_pressure_diff(me: &NeighborProperties, close: &NeighborProperties) -> f32 {
+    let (mine, theirs) = (pressure(me), pressure(close));
+    if theirs > mine {
+        close.air_pressure.0.min(theirs - mine)
     } else {
-        -me.air_pressure.0.min(
-            (-close.total_height() * gravity_factor - close.air_pressure.0
-                + me.total_height() * gravity_factor
-                + me.air_pressure.0)
-                .max(0.0),
-        )
+        -me.air_pressure.0.min(mine - theirs)
     }
 }
 
+// Wind follows the pressure, leans towards the prevailing wind and is turned
+// aside by the Coriolis force
 fn update_wind(delta: f32, neighborhood: &Neighborhood) -> Wind {
-    let wind_propagation_factor = 0.25;
-
-    let diff_up = air_pressure_diff(neighborhood.me, neighborhood.up);
-    let diff_down = air_pressure_diff(neighborhood.me, neighborhood.down);
-    let diff_left = air_pressure_diff(neighborhood.me, neighborhood.left);
-    let diff_right = air_pressure_diff(neighborhood.me, neighborhood.right);
+    let push = neighborhood.push(|link| air_pressure_diff(&neighborhood.me, &link.properties));
+    let prevailing = neighborhood.prevailing_wind;
+    let coriolis = neighborhood.coriolis;
 
     let (current_x, current_y) = neighborhood.me.wind.0.xy();
+    let (push_x, push_y) = push.xy();
+    let (prevailing_x, prevailing_y) = prevailing.xy();
 
     Wind(vec::Vec2f::new(
-        current_x + delta * wind_propagation_factor * (diff_left - diff_right - current_x),
-        current_y + delta * wind_propagation_factor * (diff_down - diff_up - current_y),
+        current_x
+            + delta
+                * (WIND_PROPAGATION_FACTOR * (push_x - current_x)
+                    + CIRCULATION_FACTOR * (prevailing_x - current_x)
+                    - coriolis * current_y),
+        current_y
+            + delta
+                * (WIND_PROPAGATION_FACTOR * (push_y - current_y)
+                    + CIRCULATION_FACTOR * (prevailing_y - current_y)
+                    + coriolis * current_x),
     ))
 }
 
 fn update_water(delta: f32, neighborhood: &Neighborhood) -> Water {
-    let water_propagation_factor = 0.5;
-
-    let diff_down = neighborhood.down.water_flow.0.get(1);
-    let diff_up = -neighborhood.up.water_flow.0.get(1);
-    let diff_left = neighborhood.left.water_flow.0.get(0);
-    let diff_right = -neighborhood.right.water_flow.0.get(0);
-
-    Water(
-        (neighborhood.me.water.0
-            + delta * water_propagation_factor * (diff_down + diff_up + diff_left + diff_right))
-            .max(0.0),
-    )
+    let water = neighborhood.me.water.0
+        + delta * WATER_PROPAGATION_FACTOR * neighborhood.inflow(|close| close.water_flow.0);
+    Water(if water < 0.0 { 0.0 } else { water })
 }
 
-fn water_diff(me: CellProperties, close: CellProperties, wind: f32) -> f32 {
+fn water_diff(me: &NeighborProperties, close: &NeighborProperties, wind: f32) -> f32 {
     let wind_factor = 0.1;
 
     if close.total_height() + wind_factor * wind > me.total_height() {
@@ -218,65 +898,100 @@ fn water_diff(me: CellProperties, close: CellProperties, wind: f32) -> f32 {
 }
 
 fn update_water_flow(delta: f32, neighborhood: &Neighborhood) -> WaterFlow {
-    let water_flow_propagation_factor = 0.9;
-
-    let (wind_x, wind_y) = neighborhood.me.wind.0.xy();
-    let (current_x, current_y) = neighborhood.me.water_flow.0.xy();
+    let me = neighborhood.me;
+    let push = neighborhood.push(|link| {
+        let wind = vec::add(&link.properties.wind.0, &me.wind.0);
+        water_diff(&me, &link.properties, -vec::dot(&wind, &link.direction()))
+    });
 
-    let diff_up = water_diff(
-        neighborhood.me,
-        neighborhood.up,
-        -neighborhood.up.wind.0.xy().1 - wind_y,
-    );
-    let diff_down = water_diff(
-        neighborhood.me,
-        neighborhood.down,
-        *neighborhood.down.wind.0.xy().1 + wind_y,
-    );
-    let diff_left = water_diff(
-        neighborhood.me,
-        neighborhood.left,
-        *neighborhood.left.wind.0.xy().0 + wind_x,
-    );
-    let diff_right = water_diff(
-        neighborhood.me,
-        neighborhood.right,
-        -neighborhood.right.wind.0.xy().0 - wind_x,
-    );
+    let (current_x, current_y) = me.water_flow.0.xy();
+    let (push_x, push_y) = push.xy();
 
     let flow = vec::Vec2f::new(
-        current_x + delta * water_flow_propagation_factor * (diff_left - diff_right - current_x),
-        current_y + delta * water_flow_propagation_factor * (diff_down - diff_up - current_y),
+        current_x + delta * WATER_FLOW_PROPAGATION_FACTOR * (push_x - current_x),
+        current_y + delta * WATER_FLOW_PROPAGATION_FACTOR * (push_y - current_y),
     );
 
-    if vec::len(&flow) < neighborhood.me.water.0 {
+    if vec::len(&flow) < me.water.0 {
         WaterFlow(flow)
     } else {
-        WaterFlow(vec::mul(neighborhood.me.water.0, &vec::normalize(&flow)))
+        WaterFlow(vec::mul(me.water.0, &vec::normalize(&flow)))
     }
 }
 
+// Heat spreads by potential temperature, comes in from the sun and radiates
+// away, all slowed down by the heat capacity of the cell
 fn update_heat(delta: f32, neighborhood: &Neighborhood) -> Heat {
-    let heat_propagation_factor = 1.0;
-    let gravity_factor = 0.2;
-    let wind_factor = 1.0;
+    let me = neighborhood.me;
+    let potential = climate::potential_temperature(&me);
+    let spread = HEAT_PROPAGATION_FACTOR * neighborhood.laplacian(climate::potential_temperature);
+    let forcing = climate::forcing(neighborhood.insolation, potential);
+    Heat(me.heat.0 + delta * (spread + forcing) / climate::heat_capacity(&me))
+}
 
-    let diff_down = neighborhood.down.heat.0 + neighborhood.down.total_height() * gravity_factor;
-    let diff_up = neighborhood.up.heat.0 + neighborhood.up.total_height() * gravity_factor;
-    let diff_left = neighborhood.left.heat.0 + neighborhood.left.total_height() * gravity_factor;
-    let diff_right = neighborhood.right.heat.0 + neighborhood.right.total_height() * gravity_factor;
+fn update_humidity(delta: f32, neighborhood: &Neighborhood) -> Humidity {
+    let humidity = neighborhood.me.humidity.0 + delta * neighborhood.advect(|cell| cell.humidity.0);
+    Humidity(if humidity < 0.0 { 0.0 } else { humidity })
+}
 
-    Heat(
-        neighborhood.me.heat.0
-            + delta
-                * heat_propagation_factor
-                * (diff_down + diff_up + diff_left + diff_right
-                    - 4.0
-                        * (neighborhood.me.heat.0
-                            + neighborhood.me.total_height() * gravity_factor)),
-    )
+// Groundwater flows down the water table
+fn update_groundwater(delta: f32, neighborhood: &Neighborhood) -> Groundwater {
+    let flow = neighborhood.exchange(|from, to, _| soil::groundwater_flow(from, to));
+    let groundwater = neighborhood.me.groundwater.0 + delta * flow;
+    Groundwater(if groundwater < 0.0 { 0.0 } else { groundwater })
+}
+
+// Glaciers creep down the slope of their surface
+fn update_ice(delta: f32, neighborhood: &Neighborhood) -> Ice {
+    let creep = neighborhood.exchange(|from, to, _| ice::flow(from, to));
+    let ice = neighborhood.me.ice.0 + delta * creep;
+    Ice(if ice < 0.0 { 0.0 } else { ice })
+}
+
+// Water averaged over about a year, so that biomes can follow the seasons
+// rather than the weather
+fn update_seasonal_water(
+    delta: f32,
+    current: &CellProperties,
+    water: &Water,
+    calendar: &climate::Calendar,
+) -> SeasonalWater {
+    let average = current.seasonal_water.0;
+    SeasonalWater(average + calendar.averaging(delta) * (water.0 - average))
+}
+
+// Snow and ice averaged over about a year, so that biomes can tell glaciers
+// from winter snow
+fn update_seasonal_cover(
+    delta: f32,
+    current: &CellProperties,
+    calendar: &climate::Calendar,
+) -> SeasonalCover {
+    let average = current.seasonal_cover.0;
+    let cover = current.snow.0 + current.ice.0;
+    SeasonalCover(average + calendar.averaging(delta) * (cover - average))
+}
+
+// 1 for a cell whose ground is soaked, 0 otherwise
+fn waterlogging(cell: &CellProperties) -> f32 {
+    if soil::is_waterlogged(cell) {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+// Part of about a year the ground of a cell has been soaked
+fn update_seasonal_wetness(
+    delta: f32,
+    current: &CellProperties,
+    calendar: &climate::Calendar,
+) -> SeasonalWetness {
+    let average = current.seasonal_wetness.0;
+    SeasonalWetness(average + calendar.averaging(delta) * (waterlogging(current) - average))
 }
 
-fn update_resources(delta: f32, neighborhood: &Neighborhood) -> Resources {
-    Resources(0.0)
+fn update_resources(delta: f32, current: &CellProperties) -> Resources {
+    let resources = current.resources.0 + delta * soil::fertility(current);
+    Resources(if resources < 0.0 { 0.0 } else { resources })
 }
@@ -0,0 +1,279 @@
+use cell;
+use ice;
+use vec;
+
+// Where a world lies on its planet
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Climate {
+    // Latitudes of the northern and southern edges, in degrees
+    pub north: f32,
+    pub south: f32,
+    // Coriolis parameter at the poles, twice how fast the planet turns per
+    // unit of time. 0 for worlds that don't turn.
+    pub rotation: f32,
+    // Whether winds lean towards the prevailing winds of the Hadley, Ferrel
+    // and polar cells
+    pub circulation: bool,
+}
+
+impl Default for Climate {
+    fn default() -> Climate {
+        Climate {
+            north: 60.0,
+            south: -60.0,
+            rotation: 0.2,
+            circulation: true,
+        }
+    }
+}
+
+// Speed of the prevailing winds
+const PREVAILING_WIND: f32 = 0.05;
+// How much slower the prevailing winds blow north and south than east and west
+const MERIDIONAL_SHARE: f32 = 0.5;
+
+// Heat a cell absorbs per unit of time with the sun straight overhead
+const SOLAR_INPUT: f32 = 0.06;
+// Heat a cell radiates away per unit of time and potential temperature
+const EMISSION: f32 = 0.05;
+// Potential temperature at which a cell radiates nothing
+const COLD: f32 = -0.6;
+// Heat air loses per unit of altitude
+pub const LAPSE_RATE: f32 = 0.6;
+// How much more heat a cell covered in water takes to warm up
+const WATER_HEAT_CAPACITY: f32 = 4.0;
+// How much warmer air lowers the pressure the wind feels
+pub const BUOYANCY: f32 = 0.2;
+
+impl Climate {
+    // Whether both edges lie on the planet
+    pub fn is_valid(&self) -> bool {
+        [self.north, self.south]
+            .iter()
+            .all(|latitude| (-90.0..=90.0).contains(latitude))
+            && self.rotation.is_finite()
+    }
+
+    // Coriolis parameter at `latitude`, how fast the wind turns to the right
+    // in the north and to the left in the south
+    pub fn coriolis(&self, latitude: f32) -> f32 {
+        self.rotation * latitude.to_radians().sin()
+    }
+
+    // Wind at the ground of the three circulation cells of each hemisphere:
+    // trade winds blowing west towards the equator up to 30 degrees, westerlies
+    // blowing east towards the poles up to 60 degrees, and polar easterlies
+    // blowing west towards the equator beyond. y grows to the south.
+    pub fn prevailing_wind(&self, latitude: f32) -> vec::Vec2f {
+        if !self.circulation {
+            return vec::Vec2f::new(0.0, 0.0);
+        }
+        let latitude = latitude.to_radians();
+        // Strongest in the middle of the westerlies and polar easterlies and
+        // at the equator, calm between the bands
+        let band = latitude.abs();
+        let zonal = if band < std::f32::consts::FRAC_PI_6 {
+            -(3.0 * band).cos()
+        } else {
+            -(6.0 * band).sin()
+        };
+        vec::Vec2f::new(
+            PREVAILING_WIND * zonal,
+            PREVAILING_WIND * MERIDIONAL_SHARE * (6.0 * latitude).sin(),
+        )
+    }
+
+    // Latitude of the centers of the cells in row `y` of `height` rows
+    pub fn latitude(&self, y: u32, height: u32) -> f32 {
+        self.north + (self.south - self.north) * (y as f32 + 0.5) / height as f32
+    }
+}
+
+// Days, years and seasons, all lengths in units of simulated time. Time 0 is
+// midnight at the northern spring equinox.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Calendar {
+    // 0 leaves out the days, the sun then gives its average over a day
+    pub day_length: f32,
+    // 0 leaves out the seasons
+    pub year_length: f32,
+    // Tilt of the planet's axis in degrees, how far north and south the sun
+    // moves over a year
+    pub axial_tilt: f32,
+}
+
+impl Default for Calendar {
+    fn default() -> Calendar {
+        Calendar {
+            day_length: 10.0,
+            year_length: 360.0,
+            axial_tilt: 23.44,
+        }
+    }
+}
+
+impl Calendar {
+    pub fn is_valid(&self) -> bool {
+        self.day_length >= 0.0 && self.year_length >= 0.0 && (0.0..=90.0).contains(&self.axial_tilt)
+    }
+
+    // Part of the day gone by at `time`, 0.5 is noon
+    pub fn time_of_day(&self, time: f64) -> f32 {
+        fraction(time, self.day_length)
+    }
+
+    // Part of the year gone by at `time`
+    pub fn time_of_year(&self, time: f64) -> f32 {
+        fraction(time, self.year_length)
+    }
+
+    // Number of the day within the year at `time`, starting at 0
+    pub fn day_of_year(&self, time: f64) -> u32 {
+        if self.day_length > 0.0 {
+            (self.time_of_year(time) * self.year_length / self.day_length) as u32
+        } else {
+            0
+        }
+    }
+
+    // Where the sun stands at `time`
+    pub fn sun(&self, time: f64) -> Sun {
+        let season = (2.0 * std::f32::consts::PI * self.time_of_year(time)).sin();
+        Sun {
+            declination: (self.axial_tilt * season).to_radians(),
+            hour_angle: if self.day_length > 0.0 {
+                Some(2.0 * std::f32::consts::PI * (self.time_of_day(time) - 0.5))
+            } else {
+                None
+            },
+        }
+    }
+
+    // Part of the way from a cell's seasonal average to its current value
+    // the average moves in `delta`
+    pub fn averaging(&self, delta: f32) -> f32 {
+        if self.year_length > 0.0 {
+            (delta / self.year_length).min(1.0)
+        } else {
+            1.0
+        }
+    }
+}
+
+fn fraction(time: f64, length: f32) -> f32 {
+    if length > 0.0 {
+        (time / f64::from(length)).fract() as f32
+    } else {
+        0.0
+    }
+}
+
+// Position of the sun in the sky, in radians
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sun {
+    // Latitude it stands straight over
+    pub declination: f32,
+    // How far the planet has turned past noon, None to average over the day
+    pub hour_angle: Option<f32>,
+}
+
+impl Sun {
+    // Sunlight reaching the ground at `latitude`, 1 for the sun straight
+    // overhead for a whole day at the equinox. Over a day the sun is up half
+    // the time at an average height of 1 / π, so it is scaled by π.
+    pub fn insolation(&self, latitude: f32) -> f32 {
+        let latitude = latitude.to_radians();
+        let high = latitude.sin() * self.declination.sin();
+        let wide = latitude.cos() * self.declination.cos();
+        match self.hour_angle {
+            Some(hour_angle) => std::f32::consts::PI * (high + wide * hour_angle.cos()).max(0.0),
+            None => {
+                // Hour angle of sunset, 0 in polar night and π in polar day
+                let sunset = if wide > 0.0 {
+                    (-high / wide).clamp(-1.0, 1.0).acos()
+                } else if high > 0.0 {
+                    std::f32::consts::PI
+                } else {
+                    0.0
+                };
+                sunset * high + wide * sunset.sin()
+            }
+        }
+    }
+}
+
+// Heat a cell gains from `insolation` less what it radiates away at
+// `potential` temperature per unit of time
+pub fn forcing(insolation: f32, potential: f32) -> f32 {
+    SOLAR_INPUT * insolation - EMISSION * (potential - COLD)
+}
+
+// Height above sea level
+pub fn altitude(height: f32) -> f32 {
+    height.max(0.0)
+}
+
+// Heat the air of a cell would have brought down to sea level. Air mixes and
+// radiates by it, so that mountains stay colder than the lowlands around them.
+pub fn potential_temperature(cell: &cell::NeighborProperties) -> f32 {
+    cell.heat.0 + LAPSE_RATE * altitude(cell.height.0)
+}
+
+// Heat it takes to warm a cell by one unit, more where it is covered in water
+pub fn heat_capacity(cell: &cell::NeighborProperties) -> f32 {
+    1.0 + WATER_HEAT_CAPACITY * (cell.water.0 / WET_DEPTH).min(1.0)
+}
+
+// Most water the air holds at a heat of 0
+const SATURATION: f32 = 0.01;
+// How much more water the air holds per unit of heat
+const SATURATION_GROWTH: f32 = 2.0;
+// Heat air loses per unit of height it is pushed up per unit of time
+const UPLIFT_COOLING: f32 = 100.0;
+// Part of what the air could still hold that evaporates per unit of time
+const EVAPORATION_RATE: f32 = 0.5;
+// Part of what the air holds too much that rains out per unit of time
+const CONDENSATION_RATE: f32 = 0.5;
+// Water that covers a cell, enough to evaporate at the full rate
+const WET_DEPTH: f32 = 0.01;
+
+// Most water air at `heat` holds
+pub fn saturation(heat: f32) -> f32 {
+    SATURATION * (SATURATION_GROWTH * heat).exp()
+}
+
+// How fast the air in a cell is pushed up the slope by the wind
+pub fn uplift(cell: &cell::CellProperties) -> f32 {
+    vec::dot(&cell.wind.0, &cell.gradient.0).max(0.0)
+}
+
+// Exchanges water between the ground and the air of every cell: water
+// evaporates into air that can hold more, and air that holds more than it can
+// rains out. Air pushed up slopes cools and can hold less, so it rains on
+// windward slopes and arrives dry behind them. Below freezing it snows.
+pub fn rain(delta: f32, fields: &mut cell::Fields) {
+    for i in 0..fields.len() {
+        let cell = fields.get(i);
+        let capacity = saturation(cell.heat.0 - UPLIFT_COOLING * uplift(&cell));
+        let humidity = cell.humidity.0;
+        let change = if humidity > capacity {
+            -(delta * CONDENSATION_RATE * (humidity - capacity)).min(humidity - capacity)
+        } else {
+            // The sea makes up for what evaporates from the ocean, lakes and
+            // puddles dry up
+            let wet = if fields.ocean[i] {
+                1.0
+            } else {
+                (cell.water.0 / WET_DEPTH).min(1.0)
+            };
+            (delta * EVAPORATION_RATE * wet * (capacity - humidity)).min(cell.water.0)
+        };
+        fields.humidity[i] = humidity + change;
+        if change < 0.0 && cell.heat.0 < ice::FREEZING {
+            fields.snow[i] = cell.snow.0 - change;
+        } else {
+            let water = cell.water.0 - change;
+            fields.water[i] = if water < 0.0 { 0.0 } else { water };
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use cell;
+use std::fmt;
+use std::str::FromStr;
+use vec;
+
+// Magnitude no field should get anywhere near while the simulation is healthy
+pub const RUNAWAY_LIMIT: f32 = 1000.0;
+
+// What `World::update` does when a check of the fields finds a problem
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Policy {
+    // Replaces bad values with the closest healthy ones and carries on
+    Clamp,
+    // Goes back to the state before the substep that went wrong and retries
+    // it with half the time step, halts if that doesn't help
+    Rollback,
+    // Stops the world, every later update returns the problem as an error
+    Halt,
+}
+
+impl FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Policy, String> {
+        match s {
+            "clamp" => Ok(Policy::Clamp),
+            "rollback" => Ok(Policy::Rollback),
+            "halt" => Ok(Policy::Halt),
+            _ => Err(format!("unknown policy: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Problem {
+    NotFinite,
+    Negative,
+    Runaway,
+}
+
+// The first bad value found in a check, `tick` counting the updates of the
+// world from 1
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Issue {
+    pub tick: u64,
+    pub x: u32,
+    pub y: u32,
+    pub field: &'static str,
+    pub value: f32,
+    pub problem: Problem,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let problem = match self.problem {
+            Problem::NotFinite => "is not finite",
+            Problem::Negative => "is negative",
+            Problem::Runaway => "ran away",
+        };
+        write!(
+            f,
+            "tick {}: {} of cell ({}, {}) {} ({})",
+            self.tick, self.field, self.x, self.y, problem, self.value
+        )
+    }
+}
+
+// Fields that can never be negative
+fn non_negative(field: &str) -> bool {
+    [
+        "water",
+        "sediment",
+        "resources",
+        "humidity",
+        "seasonal_water",
+        "seasonal_cover",
+        "seasonal_wetness",
+        "soil_moisture",
+        "groundwater",
+        "snow",
+        "ice",
+    ]
+    .contains(&field)
+}
+
+fn check_value(field: &str, value: f32) -> Option<Problem> {
+    if !value.is_finite() {
+        Some(Problem::NotFinite)
+    } else if value < 0.0 && non_negative(field) {
+        Some(Problem::Negative)
+    } else if value.abs() > RUNAWAY_LIMIT {
+        Some(Problem::Runaway)
+    } else {
+        None
+    }
+}
+
+fn check_vector(v: &vec::Vec2f) -> Option<Problem> {
+    let (x, y) = v.xy();
+    if !x.is_finite() || !y.is_finite() {
+        Some(Problem::NotFinite)
+    } else if vec::len(v) > RUNAWAY_LIMIT {
+        Some(Problem::Runaway)
+    } else {
+        None
+    }
+}
+
+// Finds the bad value of the cell with the lowest index, the tick and position
+// are left for the caller to fill in
+pub fn check(fields: &cell::Fields) -> Option<(usize, &'static str, f32, Problem)> {
+    let mut first: Option<(usize, &'static str, f32, Problem)> = None;
+    let mut keep_first = |found: (usize, &'static str, f32, Problem)| {
+        if first.is_none_or(|(i, _, _, _)| found.0 < i) {
+            first = Some(found);
+        }
+    };
+    for (field, values) in fields.scalars().iter() {
+        let found = values
+            .iter()
+            .enumerate()
+            .find_map(|(i, &value)| check_value(field, value).map(|p| (i, *field, value, p)));
+        if let Some(found) = found {
+            keep_first(found);
+        }
+    }
+    for (field, values) in fields.vectors().iter() {
+        let found = values
+            .iter()
+            .enumerate()
+            .find_map(|(i, v)| check_vector(v).map(|p| (i, *field, vec::len(v), p)));
+        if let Some(found) = found {
+            keep_first(found);
+        }
+    }
+    first
+}
+
+// Replaces bad values: non finite ones by 0, negative ones of fields that
+// can't be negative by 0 and runaway ones by the limit
+pub fn clamp(fields: &mut cell::Fields) {
+    for (field, values) in fields.scalars_mut().iter_mut() {
+        for value in values.iter_mut() {
+            *value = match check_value(field, *value) {
+                Some(Problem::NotFinite) | Some(Problem::Negative) => 0.0,
+                Some(Problem::Runaway) => RUNAWAY_LIMIT.copysign(*value),
+                None => *value,
+            };
+        }
+    }
+    for (_, values) in fields.vectors_mut().iter_mut() {
+        for v in values.iter_mut() {
+            *v = match check_vector(v) {
+                Some(Problem::NotFinite) | Some(Problem::Negative) => vec::Vec2f::new(0.0, 0.0),
+                Some(Problem::Runaway) => vec::mul(RUNAWAY_LIMIT, &vec::normalize(v)),
+                None => *v,
+            };
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use boundary;
+use cell;
+use vec;
+use water;
+use world;
+
+// Hydraulic erosion: moving water picks up ground as sediment up to what it
+// can carry, takes it along and drops it where it slows down
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Erosion {
+    // Sediment water can carry per unit of flow and slope
+    pub capacity: f32,
+    // Part of what the water could still carry that it picks up per unit of
+    // time
+    pub erosion_rate: f32,
+    // Part of what the water carries too much that it drops per unit of time
+    pub deposition_rate: f32,
+}
+
+impl Default for Erosion {
+    fn default() -> Erosion {
+        Erosion {
+            capacity: 4.0,
+            erosion_rate: 0.05,
+            deposition_rate: 0.2,
+        }
+    }
+}
+
+// Slope used for flat ground, so that water flowing over it still carries a
+// little
+const MIN_SLOPE: f32 = 0.01;
+
+impl Erosion {
+    pub fn none() -> Erosion {
+        Erosion {
+            capacity: 0.0,
+            erosion_rate: 0.0,
+            deposition_rate: 0.0,
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.erosion_rate == 0.0 && self.deposition_rate == 0.0
+    }
+
+    // Whether the capacity and rates are all numbers that are not negative
+    pub fn is_valid(&self) -> bool {
+        [self.capacity, self.erosion_rate, self.deposition_rate]
+            .iter()
+            .all(|value| value.is_finite() && *value >= 0.0)
+    }
+
+    // Largest time step that doesn't pick up or drop more than the difference
+    // to the capacity
+    pub fn stable_delta(&self) -> f32 {
+        1.0 / self.erosion_rate.max(self.deposition_rate)
+    }
+}
+
+// Thermal erosion: ground on slopes steeper than the talus angle slumps down
+// to its neighbors until the slope settles at the angle
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Talus {
+    // Steepest slope ground stands at, in degrees, with heights measured in
+    // the same units as the distance between cells
+    pub angle: f32,
+    // Part of the height difference above the angle that slumps per unit of
+    // time
+    pub rate: f32,
+}
+
+impl Default for Talus {
+    fn default() -> Talus {
+        Talus {
+            angle: 10.0,
+            rate: 0.5,
+        }
+    }
+}
+
+impl Talus {
+    pub fn none() -> Talus {
+        Talus {
+            angle: 90.0,
+            rate: 0.0,
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.rate == 0.0
+    }
+
+    // Height difference per unit of distance the ground stands at
+    pub fn max_slope(&self) -> f32 {
+        self.angle.to_radians().tan()
+    }
+
+    // Largest time step that doesn't slump a cell below its neighbors
+    pub fn stable_delta(&self, stencil: cell::Stencil) -> f32 {
+        1.0 / (self.rate * stencil.weight_sum())
+    }
+}
+
+// Fills in the outflows through the links of the cells starting at `start`
+// for the flow water model, which doesn't keep them: each link carries the
+// part of `water_flow` along it, scaled down so that no cell loses more water
+// in `delta` than it has
+pub fn flow_outflows(
+    world: &world::World,
+    water_flow: &[vec::Vec2f],
+    delta: f32,
+    start: usize,
+    next: &mut [[f32; cell::MAX_LINKS]],
+) {
+    let fields = &world.fields;
+    for (j, outflow) in next.iter_mut().enumerate() {
+        let i = start + j;
+        let (x, y) = (i as u32 % world.width, i as u32 / world.width);
+        let mut total = 0.0;
+        *outflow = [0.0; cell::MAX_LINKS];
+        for (k, &(dx, dy, weight)) in world.stencil.links(y).iter().enumerate() {
+            let through = match world.boundary.resolve(
+                x as i32 + dx,
+                y as i32 + dy,
+                world.width,
+                world.height,
+            ) {
+                boundary::Neighbor::Cell(..) => true,
+                boundary::Neighbor::Outside(edge) => edge == boundary::Edge::Open,
+            };
+            if through {
+                let along = vec::dot(&water_flow[i], &world.stencil.offset(y, dx, dy));
+                outflow[k] = weight * along.max(0.0);
+                total += outflow[k];
+            }
+        }
+        if total * delta > fields.water[i] {
+            let scale = fields.water[i] / (total * delta);
+            for flow in outflow.iter_mut() {
+                *flow *= scale;
+            }
+        }
+    }
+}
+
+// Moves the sediment of the cells starting at `start` along with the water,
+// see `water::carry`, then erodes or deposits towards what the new
+// `water_flow` can carry. Builds on the height and sediment the cells stepped
+// to in `next`.
+pub fn transport(
+    world: &world::World,
+    outflow: &[[f32; cell::MAX_LINKS]],
+    water_flow: &[vec::Vec2f],
+    delta: f32,
+    start: usize,
+    next: (&mut [f32], &mut [f32]),
+) {
+    let (fields, erosion) = (&world.fields, &world.erosion);
+    let (height, sediment) = next;
+    water::carry(world, outflow, delta, start, sediment, |i| {
+        fields.sediment[i]
+    });
+    for j in 0..height.len() {
+        let i = start + j;
+        let load = sediment[j];
+        let slope = vec::len(&fields.gradient[i]).max(MIN_SLOPE);
+        let capacity = erosion.capacity * vec::len(&water_flow[i]) * slope;
+        let ground = height[j];
+        let change = if load < capacity {
+            -(delta * erosion.erosion_rate * (capacity - load)).min(ground + 1.0)
+        } else {
+            (delta * erosion.deposition_rate * (load - capacity)).min(1.0 - ground)
+        };
+        // The sediment takes what the ground really lost after rounding, a
+        // change too small to show in the height must not create sediment
+        height[j] = ground + change;
+        let left = load - (height[j] - ground);
+        sediment[j] = if left < 0.0 { 0.0 } else { left };
+    }
+}
@@ -0,0 +1,135 @@
+use noise;
+use random;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FractalKind {
+    Fbm,
+    Ridged,
+    Billow,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Fractal {
+    pub kind: FractalKind,
+    pub octaves: u32,
+    pub lacunarity: f32,
+    pub persistence: f32,
+}
+
+impl Default for Fractal {
+    fn default() -> Fractal {
+        Fractal {
+            kind: FractalKind::Fbm,
+            octaves: 1,
+            lacunarity: 2.0,
+            persistence: 0.5,
+        }
+    }
+}
+
+// One noise source per octave. Every octave covers the same area, with its
+// grid size scaled by the lacunarity, so the sum still tiles seamlessly if the
+// sources do. The grid sizes are rounded along each axis on its own, so each
+// octave keeps a scale per axis.
+pub struct FractalSource {
+    fractal: Fractal,
+    octaves: Vec<(Box<dyn noise::NoiseSource>, (f32, f32))>,
+}
+
+impl FractalSource {
+    pub fn new(
+        fractal: Fractal,
+        kind: noise::NoiseKind,
+        width: u32,
+        height: u32,
+        rng: &mut random::Rng,
+    ) -> FractalSource {
+        let mut frequency = 1.0;
+        let octaves = (0..fractal.octaves.max(1))
+            .map(|_| {
+                let octave_width = ((width as f32 * frequency).round() as u32).max(1);
+                let octave_height = ((height as f32 * frequency).round() as u32).max(1);
+                frequency *= fractal.lacunarity;
+                (
+                    noise::new_source(kind, octave_width, octave_height, rng),
+                    (
+                        octave_width as f32 / width as f32,
+                        octave_height as f32 / height as f32,
+                    ),
+                )
+            })
+            .collect();
+        FractalSource { fractal, octaves }
+    }
+}
+
+impl noise::NoiseSource for FractalSource {
+    fn size(&self) -> (u32, u32) {
+        self.octaves[0].0.size()
+    }
+
+    // Samples a point given in grid units of the first octave, the result is
+    // in roughly the same range as a single octave of the source.
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        let mut amplitude = 1.0;
+        let mut total_amplitude = 0.0;
+        let mut total = 0.0;
+        for (grid, (scale_x, scale_y)) in self.octaves.iter() {
+            let n = grid.sample(x * scale_x, y * scale_y);
+            let value = match self.fractal.kind {
+                FractalKind::Fbm => n,
+                FractalKind::Ridged => {
+                    let ridge = (1.0 - 2.0 * n.abs()).max(0.0);
+                    ridge * ridge - 0.5
+                }
+                FractalKind::Billow => 2.0 * n.abs() - 0.5,
+            };
+            total += amplitude * value;
+            total_amplitude += amplitude;
+            amplitude *= self.fractal.persistence;
+        }
+        total / total_amplitude
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use noise::NoiseSource;
+
+    #[test]
+    fn octaves_tile_with_any_lacunarity() {
+        let fractal = Fractal {
+            octaves: 4,
+            lacunarity: 1.7,
+            ..Fractal::default()
+        };
+        let (width, height) = (4, 3);
+        let source = FractalSource::new(
+            fractal,
+            noise::NoiseKind::Gradient,
+            width,
+            height,
+            &mut random::Rng::new(1),
+        );
+        for i in 0..50 {
+            let (x, y) = (0.37 * i as f32 % 4.0, 0.23 * i as f32 % 3.0);
+            let value = source.sample(x, y);
+            for &(dx, dy) in [(width, 0), (0, height)].iter() {
+                let wrapped = source.sample(x + dx as f32, y + dy as f32);
+                assert!(
+                    (value - wrapped).abs() < 1e-4,
+                    "({}, {}) + ({}, {}): {} against {}",
+                    x,
+                    y,
+                    dx,
+                    dy,
+                    value,
+                    wrapped
+                );
+            }
+        }
+    }
+}
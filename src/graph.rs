@@ -0,0 +1,306 @@
+use fractal;
+use noise;
+use noise::NoiseSource;
+use random;
+use utils;
+
+// A tree of noise sources and operations describing one field of a world.
+// Nodes are sampled in grid units of the world, like the plain noise sources,
+// and can be stored as json so terrain can be shaped without recompiling.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Node {
+    Noise {
+        #[serde(default)]
+        kind: noise::NoiseKind,
+        #[serde(default)]
+        fractal: fractal::Fractal,
+        #[serde(default = "default_frequency")]
+        frequency: f32,
+    },
+    Constant {
+        value: f32,
+    },
+    Add {
+        inputs: Vec<Node>,
+    },
+    Multiply {
+        inputs: Vec<Node>,
+    },
+    Clamp {
+        input: Box<Node>,
+        min: f32,
+        max: f32,
+    },
+    // Piecewise linear remapping through the given (input, output) points
+    Curve {
+        input: Box<Node>,
+        points: Vec<(f32, f32)>,
+    },
+    // Offsets the position `input` is sampled at by the values of `x` and `y`
+    Warp {
+        input: Box<Node>,
+        x: Box<Node>,
+        y: Box<Node>,
+        strength: f32,
+    },
+    // 1 inside `radius` around the center, falling smoothly to 0 over `width`.
+    // Distances are relative, 1 being the distance from the center to an edge.
+    IslandMask {
+        radius: f32,
+        width: f32,
+    },
+    // Quantizes the input, assumed to be in [-1, 1], into `steps` levels. The
+    // first `flatness` part of each step is flat, the rest ramps up to the next.
+    Terrace {
+        input: Box<Node>,
+        steps: u32,
+        flatness: f32,
+    },
+}
+
+fn default_frequency() -> f32 {
+    1.0
+}
+
+impl Node {
+    pub fn noise(layer: noise::NoiseLayer) -> Node {
+        Node::Noise {
+            kind: layer.kind,
+            fractal: layer.fractal,
+            frequency: 1.0,
+        }
+    }
+
+    // Creates the noise sources of the graph, drawing from `rng` depth first
+    pub fn build(&self, width: u32, height: u32, rng: &mut random::Rng) -> Box<dyn NoiseSource> {
+        let size = (width, height);
+        let build_all = |inputs: &[Node], rng: &mut random::Rng| -> Vec<Box<dyn NoiseSource>> {
+            inputs
+                .iter()
+                .map(|input| input.build(width, height, rng))
+                .collect()
+        };
+        match self {
+            Node::Noise {
+                kind,
+                fractal,
+                frequency,
+            } => {
+                let scaled_width = ((width as f32 * frequency).round() as u32).max(1);
+                let scaled_height = ((height as f32 * frequency).round() as u32).max(1);
+                Box::new(Scaled {
+                    size,
+                    scale: (
+                        scaled_width as f32 / width as f32,
+                        scaled_height as f32 / height as f32,
+                    ),
+                    source: Box::new(fractal::FractalSource::new(
+                        *fractal,
+                        *kind,
+                        scaled_width,
+                        scaled_height,
+                        rng,
+                    )),
+                })
+            }
+            Node::Constant { value } => Box::new(Constant {
+                size,
+                value: *value,
+            }),
+            Node::Add { inputs } => Box::new(Combine {
+                size,
+                inputs: build_all(inputs, rng),
+                multiply: false,
+            }),
+            Node::Multiply { inputs } => Box::new(Combine {
+                size,
+                inputs: build_all(inputs, rng),
+                multiply: true,
+            }),
+            Node::Clamp { input, min, max } => Box::new(Map {
+                input: input.build(width, height, rng),
+                operation: Operation::Clamp(*min, *max),
+            }),
+            Node::Curve { input, points } => {
+                let mut points = points.clone();
+                points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+                Box::new(Map {
+                    input: input.build(width, height, rng),
+                    operation: Operation::Curve(points),
+                })
+            }
+            Node::Warp {
+                input,
+                x,
+                y,
+                strength,
+            } => Box::new(Warp {
+                input: input.build(width, height, rng),
+                x: x.build(width, height, rng),
+                y: y.build(width, height, rng),
+                strength: *strength,
+            }),
+            Node::IslandMask {
+                radius,
+                width: falloff,
+            } => Box::new(IslandMask {
+                size,
+                radius: *radius,
+                width: *falloff,
+            }),
+            Node::Terrace {
+                input,
+                steps,
+                flatness,
+            } => Box::new(Map {
+                input: input.build(width, height, rng),
+                operation: Operation::Terrace((*steps).max(1), flatness.clamp(0.0, 0.99)),
+            }),
+        }
+    }
+}
+
+struct Scaled {
+    size: (u32, u32),
+    scale: (f32, f32),
+    source: Box<dyn NoiseSource>,
+}
+
+impl NoiseSource for Scaled {
+    fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        self.source.sample(x * self.scale.0, y * self.scale.1)
+    }
+}
+
+struct Constant {
+    size: (u32, u32),
+    value: f32,
+}
+
+impl NoiseSource for Constant {
+    fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    fn sample(&self, _x: f32, _y: f32) -> f32 {
+        self.value
+    }
+}
+
+struct Combine {
+    size: (u32, u32),
+    inputs: Vec<Box<dyn NoiseSource>>,
+    multiply: bool,
+}
+
+impl NoiseSource for Combine {
+    fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        if self.multiply {
+            self.inputs
+                .iter()
+                .fold(1.0, |acc, input| acc * input.sample(x, y))
+        } else {
+            self.inputs
+                .iter()
+                .fold(0.0, |acc, input| acc + input.sample(x, y))
+        }
+    }
+}
+
+enum Operation {
+    Clamp(f32, f32),
+    Curve(Vec<(f32, f32)>),
+    Terrace(u32, f32),
+}
+
+struct Map {
+    input: Box<dyn NoiseSource>,
+    operation: Operation,
+}
+
+impl NoiseSource for Map {
+    fn size(&self) -> (u32, u32) {
+        self.input.size()
+    }
+
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        let value = self.input.sample(x, y);
+        match self.operation {
+            Operation::Clamp(min, max) => value.max(min).min(max),
+            Operation::Curve(ref points) => curve(points, value),
+            Operation::Terrace(steps, flatness) => {
+                let t = (value + 1.0) / 2.0 * steps as f32;
+                let step = t.floor();
+                let ramp = ((t - step - flatness) / (1.0 - flatness)).max(0.0);
+                (step + ramp) / steps as f32 * 2.0 - 1.0
+            }
+        }
+    }
+}
+
+fn curve(points: &[(f32, f32)], value: f32) -> f32 {
+    match (points.first(), points.last()) {
+        (Some(first), _) if value <= first.0 => first.1,
+        (_, Some(last)) if value >= last.0 => last.1,
+        (None, _) => value,
+        _ => {
+            let i = points.iter().position(|p| p.0 > value).unwrap_or(1);
+            let (from, to) = (points[i - 1], points[i]);
+            utils::map_range(from.0..to.0, from.1..to.1, value)
+        }
+    }
+}
+
+struct Warp {
+    input: Box<dyn NoiseSource>,
+    x: Box<dyn NoiseSource>,
+    y: Box<dyn NoiseSource>,
+    strength: f32,
+}
+
+impl NoiseSource for Warp {
+    fn size(&self) -> (u32, u32) {
+        self.input.size()
+    }
+
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        self.input.sample(
+            x + self.strength * self.x.sample(x, y),
+            y + self.strength * self.y.sample(x, y),
+        )
+    }
+}
+
+struct IslandMask {
+    size: (u32, u32),
+    radius: f32,
+    width: f32,
+}
+
+impl NoiseSource for IslandMask {
+    fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        let u = x / self.size.0 as f32 * 2.0 - 1.0;
+        let v = y / self.size.1 as f32 * 2.0 - 1.0;
+        let distance = (u * u + v * v).sqrt();
+        if distance <= self.radius {
+            1.0
+        } else if distance >= self.radius + self.width {
+            0.0
+        } else {
+            1.0 - utils::interpolate(0.0, 1.0, (distance - self.radius) / self.width)
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use cell;
+
+// Heat below which rain falls as snow and above which snow and ice melt
+pub const FREEZING: f32 = 0.0;
+// Part of the snow that is pressed into ice per unit of time
+const COMPACTION_RATE: f32 = 0.005;
+// Snow and ice melting per unit of time and heat above freezing
+const MELT_RATE: f32 = 0.01;
+// Ice creeping to a neighbor per unit of time, fall of the ice surface and ice
+const ICE_FLOW: f32 = 0.02;
+// Ground scraped off per unit of ice creeping out of a cell
+pub const SCOURING: f32 = 0.01;
+
+// Height of the top of the ice of a cell
+pub fn surface(cell: &cell::NeighborProperties) -> f32 {
+    cell.height.0 + cell.ice.0
+}
+
+// Ice creeping from `from` to `to` per unit of time and link weight: down the
+// slope of the ice surface, from the ice of the higher cell
+pub fn flow(from: &cell::NeighborProperties, to: &cell::NeighborProperties) -> f32 {
+    let fall = (surface(from) - surface(to)).clamp(0.0, 1.0);
+    ICE_FLOW * fall * from.ice.0
+}
+
+// Largest time step that doesn't move more ice out of a cell than it has
+pub fn stable_delta(stencil: cell::Stencil) -> f32 {
+    1.0 / (ICE_FLOW * stencil.weight_sum())
+}
+
+// Presses the snow of every cell into ice over time, and melts snow and then
+// ice into water where it is warmer than freezing
+pub fn thaw(delta: f32, fields: &mut cell::Fields) {
+    for i in 0..fields.len() {
+        let (mut snow, mut ice) = (fields.snow[i], fields.ice[i]);
+
+        let compaction = snow * (delta * COMPACTION_RATE).min(1.0);
+        snow -= compaction;
+        ice += compaction;
+
+        let mut melt = delta * MELT_RATE * (fields.heat[i] - FREEZING).max(0.0);
+        let melted_snow = melt.min(snow);
+        snow -= melted_snow;
+        melt -= melted_snow;
+        let melted_ice = melt.min(ice);
+        ice -= melted_ice;
+
+        fields.snow[i] = snow;
+        fields.ice[i] = ice;
+        fields.water[i] += melted_snow + melted_ice;
+    }
+}
@@ -1,19 +1,37 @@
-#[allow(dead_code)]
 extern crate cfg_if;
 extern crate image;
-//extern crate rand;
-extern crate js_sys;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+#[cfg(feature = "wasm")]
 extern crate wasm_bindgen;
 
-mod biome;
-mod cell;
-mod perlin;
-mod utils;
-mod vec;
-mod world;
+pub mod biome;
+pub mod boundary;
+pub mod cell;
+pub mod climate;
+pub mod diagnostics;
+pub mod erosion;
+pub mod fractal;
+pub mod graph;
+pub mod ice;
+pub mod noise;
+pub mod perlin;
+pub mod random;
+pub mod sea;
+pub mod simplex;
+pub mod soil;
+pub mod utils;
+pub mod value;
+pub mod vec;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod water;
+pub mod world;
+pub mod worley;
 
 use cfg_if::cfg_if;
-use wasm_bindgen::prelude::*;
 
 cfg_if! {
     // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
@@ -24,153 +42,3 @@ cfg_if! {
         static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
     }
 }
-
-static mut current_world: Option<world::World> = None;
-
-#[wasm_bindgen]
-extern "C" {
-    fn alert(s: &str);
-}
-
-#[wasm_bindgen]
-pub fn create(x: f32, y: f32) {
-    unsafe {
-        current_world = Some(world::World::new(4, 100));
-    }
-}
-
-#[wasm_bindgen]
-pub fn tick(dt: f32) {
-    unsafe {
-        match current_world {
-            Some(ref mut world) => world.update(dt),
-            _ => (),
-        }
-    }
-}
-
-#[wasm_bindgen]
-pub fn size() -> Vec<u32> {
-    unsafe {
-        let (x, y) = match current_world {
-            Some(ref world) => world.size(),
-            _ => (0, 0),
-        };
-        vec![x, y]
-    }
-}
-
-#[wasm_bindgen]
-pub fn get_pixels(
-    draw_height: bool,
-    draw_water: bool,
-    draw_air_pressure: bool,
-    draw_biomes: bool,
-) -> Vec<u8> {
-    unsafe {
-        if let Some(ref world) = current_world {
-            let (width, height) = world.size();
-            let mut props = vec![0; (width * height * 3) as usize];
-            for (i, cell) in world.cells.iter().enumerate() {
-                let mut r = 125;
-                let mut g = 125;
-                let mut b = 125;
-
-                if draw_height {
-                    let h = (cell.properties.height.0 + 1.0) * 255.0 / 2.0;
-                    r = h as u8;
-                    g = h as u8;
-                    b = h as u8;
-                }
-
-                if draw_water {
-                    let w = cell.properties.water.0;
-                    r = (r as f32 * (1.0 - w)).max(0.0) as u8;
-                    b = (b as f32 * (1.0 - w) + 255.0 * w * 5.0).max(0.0).min(255.0) as u8;
-                    g = (g as f32 * (1.0 - w)).max(0.0) as u8;
-                }
-
-                if draw_air_pressure {
-                    let mut p = cell.properties.air_pressure.0 / 2.0;
-                    p = p * p * p;
-                    r = (r as f32 * (1.0 - p)).max(0.0).min(255.0) as u8;
-                    g = (g as f32 * (1.0 - p) + p * 255.0).min(255.0).max(0.0) as u8;
-                    b = (b as f32 * (1.0 - p)).max(0.0).min(255.0) as u8;
-                }
-
-                if draw_biomes {
-                    let (br, bg, bb) = biome::tmp_colors(biome::classify_tags(cell.biome_tags));
-                    r = br;
-                    g = bg;
-                    b = bb;
-                }
-
-                props[3 * i] = r;
-                props[3 * i + 1] = g;
-                props[3 * i + 2] = b;
-            }
-            props
-        } else {
-            vec![]
-        }
-    }
-}
-
-#[wasm_bindgen]
-pub fn get_heights(with_water: bool) -> Vec<f32> {
-    unsafe {
-        if let Some(ref world) = current_world {
-            let (width, height) = world.size();
-            let mut heights = vec![0.0; (width * height) as usize];
-            for (i, cell) in world.cells.iter().enumerate() {
-                if with_water {
-                    heights[i] = cell.properties.total_height();
-                } else {
-                    heights[i] = cell.properties.height.0;
-                };
-            }
-            heights
-        } else {
-            vec![]
-        }
-    }
-}
-
-#[wasm_bindgen]
-pub fn get_wind_directions() -> Vec<f32> {
-    unsafe {
-        if let Some(ref world) = current_world {
-            let (width, height) = world.size();
-            let mut directions = vec![0.0; (width * height * 2) as usize];
-            for (i, cell) in world.cells.iter().enumerate() {
-                directions[2 * i] = *cell.properties.wind.0.xy().0;
-                directions[2 * i + 1] = *cell.properties.wind.0.xy().1;
-            }
-            directions
-        } else {
-            vec![]
-        }
-    }
-}
-
-#[wasm_bindgen]
-pub fn alter_world(
-    center_x: u32,
-    center_y: u32,
-    radius: u32,
-    d_water: f32,
-    d_air_pressure: f32,
-    d_height: f32,
-) {
-    unsafe {
-        if let Some(ref mut world) = current_world {
-            let indices = world.select_cells((center_x, center_y), radius);
-            for i in indices.iter() {
-                world.cells[*i].properties =
-                    world.cells[*i]
-                        .properties
-                        .alter_properties(d_water, d_air_pressure, d_height);
-            }
-        }
-    }
-}
@@ -0,0 +1,480 @@
+extern crate genby;
+
+use genby::biome;
+use genby::boundary;
+use genby::cell;
+use genby::climate;
+use genby::diagnostics;
+use genby::erosion;
+use genby::fractal;
+use genby::noise;
+use genby::soil;
+use genby::water;
+use genby::world;
+use genby::worley;
+use std::env;
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "Usage: genby [options]
+
+Generates a world, runs it for a number of ticks and writes the layers as images.
+
+Options:
+    --width <n>     Width of the world in cells (default 100)
+    --height <n>    Height of the world in cells (default 100)
+    --size <n>      Sets both width and height
+    --grid <n>      Number of noise grid cells along the width (default 4)
+    --seed <n>      Seed used for world generation (default 0)
+    --heightmap-noise <k>
+    --heatmap-noise <k>
+    --waterlevel-noise <k>
+                    Noise each field is generated from: gradient, simplex, value,
+                    worley, worley-edges or worley-cells (default gradient)
+    --fractal <k>   Heightmap fractal: fbm, ridged or billow (default fbm)
+    --octaves <n>   Number of heightmap noise octaves (default 1)
+    --lacunarity <f>
+                    Frequency multiplier between octaves (default 2.0)
+    --persistence <f>
+                    Amplitude multiplier between octaves (default 0.5)
+    --config <file> Json file with the node graphs for the world fields, cannot be
+                    combined with the noise and fractal options above
+    --dump-config   Print the world config as json and exit
+    --boundary <b>  Boundary preset: torus, cylinder, closed or open (default torus)
+    --north <e>
+    --south <e>
+    --east <e>
+    --west <e>      Override one edge: wrap, closed, open or inflow:<depth>
+    --stencil <s>   Neighbors cells exchange with: von-neumann (4), moore (8)
+                    or hex (6, odd rows shifted half a cell east)
+                    (default von-neumann)
+    --ticks <n>     Number of ticks to simulate (default 100)
+    --dt <f>        Time step passed to each tick, split into substeps when it is
+                    too large to be stable, time that doesn't fit in 64 substeps
+                    is skipped (default 0.2)
+    --every <n>     Also write the layers every n ticks (default 0, only at the end)
+    --water <m>     How water moves: pipes, which conserves water, or flow, the
+                    original flow field model (default pipes)
+    --erosion <f>   Scales how fast water erodes and deposits the ground, 0 turns
+                    erosion off (default 1)
+    --talus-angle <a>
+                    Steepest slope in degrees the ground stands at before it
+                    slumps (default 10)
+    --talus-rate <f>
+                    How fast steeper slopes slump, 0 turns it off (default 0.5)
+    --north-latitude <deg>
+                    Latitude of the northern edge (default 60)
+    --south-latitude <deg>
+                    Latitude of the southern edge (default -60)
+    --rotation <f>  Coriolis parameter at the poles, 0 for a world that doesn't
+                    turn (default 0.2)
+    --no-circulation
+                    Leave out the prevailing winds of the Hadley, Ferrel and
+                    polar cells
+    --day-length <f>
+                    Simulated time in a day, 0 averages the sun over the day
+                    (default 10)
+    --year-length <f>
+                    Simulated time in a year, 0 turns the seasons off (default 360)
+    --axial-tilt <deg>
+                    Tilt of the planet's axis, how strong the seasons are
+                    (default 23.44)
+    --seasonal-biomes
+                    Classify biomes by the water, snow and ice cells have and
+                    how soaked their ground is over the year rather than at the end
+    --sea-level <f> Height of the sea, below which cells connected to an edge
+                    are ocean (default no sea)
+    --sea-level-rate <f>
+                    How fast the sea rises per unit of time, negative to fall
+                    (default 0)
+    --policy <p>    What to do when a field becomes NaN, negative or runs away:
+                    clamp, rollback or halt (default halt)
+    --threads <n>   Number of threads to simulate on, the result is the same for
+                    any number (default the number of cores)
+    --out <dir>     Directory to write images to (default .)
+    -h, --help      Print this message";
+
+type Layer = (&'static str, fn(&cell::Cell) -> (u8, u8, u8));
+
+struct Options {
+    width: u32,
+    height: u32,
+    grid: u32,
+    seed: u32,
+    config: world::WorldConfig,
+    boundary: boundary::Boundary,
+    stencil: cell::Stencil,
+    ticks: u32,
+    dt: f32,
+    every: u32,
+    threads: usize,
+    policy: diagnostics::Policy,
+    water: water::WaterModel,
+    erosion: f32,
+    talus: erosion::Talus,
+    climate: climate::Climate,
+    calendar: climate::Calendar,
+    seasonal_biomes: bool,
+    sea_level: Option<f32>,
+    sea_level_rate: f32,
+    out: String,
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("missing value for {}", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", flag, value))
+}
+
+fn parse_noise_kind(flag: &str, value: Option<String>) -> Result<noise::NoiseKind, String> {
+    match parse_value::<String>(flag, value)?.as_str() {
+        "gradient" => Ok(noise::NoiseKind::Gradient),
+        "simplex" => Ok(noise::NoiseKind::Simplex),
+        "value" => Ok(noise::NoiseKind::Value),
+        "worley" => Ok(noise::NoiseKind::Worley(worley::WorleyMode::Distance)),
+        "worley-edges" => Ok(noise::NoiseKind::Worley(worley::WorleyMode::Edges)),
+        "worley-cells" => Ok(noise::NoiseKind::Worley(worley::WorleyMode::Cells)),
+        kind => Err(format!("unknown noise kind: {}", kind)),
+    }
+}
+
+fn load_config(path: &str) -> Result<world::WorldConfig, String> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|err| format!("could not read config {}: {}", path, err))?;
+    world::WorldConfig::from_json(&json).map_err(|err| format!("invalid config {}: {}", path, err))
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut layers = world::WorldLayers::default();
+    let mut custom_layers = false;
+    let mut config_path: Option<String> = None;
+    let mut dump_config = false;
+    let mut options = Options {
+        width: 100,
+        height: 100,
+        grid: 4,
+        seed: 0,
+        config: world::WorldConfig::default(),
+        boundary: boundary::Boundary::default(),
+        stencil: cell::Stencil::VonNeumann,
+        ticks: 100,
+        dt: 0.2,
+        every: 0,
+        threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        policy: diagnostics::Policy::Halt,
+        water: water::WaterModel::Pipes,
+        erosion: 1.0,
+        talus: erosion::Talus::default(),
+        climate: climate::Climate::default(),
+        calendar: climate::Calendar::default(),
+        seasonal_biomes: false,
+        sea_level: None,
+        sea_level_rate: 0.0,
+        out: String::from("."),
+    };
+    let mut args = env::args().skip(1);
+    while let Some(flag) = args.next() {
+        custom_layers |= flag.ends_with("-noise")
+            || ["--fractal", "--octaves", "--lacunarity", "--persistence"].contains(&flag.as_str());
+        match flag.as_str() {
+            "--width" => options.width = parse_value(&flag, args.next())?,
+            "--height" => options.height = parse_value(&flag, args.next())?,
+            "--size" => {
+                options.width = parse_value(&flag, args.next())?;
+                options.height = options.width;
+            }
+            "--grid" => options.grid = parse_value(&flag, args.next())?,
+            "--seed" => options.seed = parse_value(&flag, args.next())?,
+            "--heightmap-noise" => layers.heightmap.kind = parse_noise_kind(&flag, args.next())?,
+            "--heatmap-noise" => layers.heatmap.kind = parse_noise_kind(&flag, args.next())?,
+            "--waterlevel-noise" => layers.waterlevel.kind = parse_noise_kind(&flag, args.next())?,
+            "--fractal" => {
+                layers.heightmap.fractal.kind =
+                    match parse_value::<String>(&flag, args.next())?.as_str() {
+                        "fbm" => fractal::FractalKind::Fbm,
+                        "ridged" => fractal::FractalKind::Ridged,
+                        "billow" => fractal::FractalKind::Billow,
+                        kind => return Err(format!("unknown fractal kind: {}", kind)),
+                    }
+            }
+            "--octaves" => layers.heightmap.fractal.octaves = parse_value(&flag, args.next())?,
+            "--lacunarity" => {
+                layers.heightmap.fractal.lacunarity = parse_value(&flag, args.next())?
+            }
+            "--persistence" => {
+                layers.heightmap.fractal.persistence = parse_value(&flag, args.next())?
+            }
+            "--config" => config_path = Some(parse_value(&flag, args.next())?),
+            "--dump-config" => dump_config = true,
+            "--boundary" => {
+                let name: String = parse_value(&flag, args.next())?;
+                options.boundary = boundary::Boundary::preset(&name)
+                    .ok_or_else(|| format!("unknown boundary preset: {}", name))?;
+            }
+            "--north" => options.boundary.north = parse_value(&flag, args.next())?,
+            "--south" => options.boundary.south = parse_value(&flag, args.next())?,
+            "--east" => options.boundary.east = parse_value(&flag, args.next())?,
+            "--west" => options.boundary.west = parse_value(&flag, args.next())?,
+            "--stencil" => options.stencil = parse_value(&flag, args.next())?,
+            "--ticks" => options.ticks = parse_value(&flag, args.next())?,
+            "--dt" => options.dt = parse_value(&flag, args.next())?,
+            "--every" => options.every = parse_value(&flag, args.next())?,
+            "--threads" => options.threads = parse_value(&flag, args.next())?,
+            "--policy" => options.policy = parse_value(&flag, args.next())?,
+            "--water" => options.water = parse_value(&flag, args.next())?,
+            "--erosion" => options.erosion = parse_value(&flag, args.next())?,
+            "--talus-angle" => options.talus.angle = parse_value(&flag, args.next())?,
+            "--talus-rate" => options.talus.rate = parse_value(&flag, args.next())?,
+            "--north-latitude" => options.climate.north = parse_value(&flag, args.next())?,
+            "--south-latitude" => options.climate.south = parse_value(&flag, args.next())?,
+            "--rotation" => options.climate.rotation = parse_value(&flag, args.next())?,
+            "--no-circulation" => options.climate.circulation = false,
+            "--day-length" => options.calendar.day_length = parse_value(&flag, args.next())?,
+            "--year-length" => options.calendar.year_length = parse_value(&flag, args.next())?,
+            "--axial-tilt" => options.calendar.axial_tilt = parse_value(&flag, args.next())?,
+            "--seasonal-biomes" => options.seasonal_biomes = true,
+            "--sea-level" => options.sea_level = Some(parse_value(&flag, args.next())?),
+            "--sea-level-rate" => options.sea_level_rate = parse_value(&flag, args.next())?,
+            "--out" => options.out = parse_value(&flag, args.next())?,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => return Err(format!("unknown option: {}", flag)),
+        }
+    }
+    options.boundary.check(options.stencil, options.height)?;
+    if options.height == 0 {
+        return Err(String::from("--height must be at least 1"));
+    }
+    if options.erosion.is_nan() || options.erosion < 0.0 {
+        return Err(String::from("--erosion must not be negative"));
+    }
+    if !(options.talus.angle > 0.0 && options.talus.angle < 90.0) {
+        return Err(String::from("--talus-angle must be between 0 and 90"));
+    }
+    if options.talus.rate.is_nan() || options.talus.rate < 0.0 {
+        return Err(String::from("--talus-rate must not be negative"));
+    }
+    if !options.climate.is_valid() {
+        return Err(String::from(
+            "--north-latitude and --south-latitude must be between -90 and 90 and --rotation finite",
+        ));
+    }
+    if !options.calendar.is_valid() {
+        return Err(String::from(
+            "--day-length and --year-length must not be negative and --axial-tilt must be between 0 and 90",
+        ));
+    }
+    if !options.sea_level.is_none_or(f32::is_finite) || !options.sea_level_rate.is_finite() {
+        return Err(String::from(
+            "--sea-level and --sea-level-rate must be finite",
+        ));
+    }
+    if options.grid == 0 || options.width < options.grid {
+        return Err(String::from("--grid must be between 1 and --width"));
+    }
+    options.config = match config_path {
+        Some(ref _path) if custom_layers => {
+            return Err(String::from(
+                "--config cannot be combined with the noise and fractal options",
+            ))
+        }
+        Some(ref path) => load_config(path)?,
+        None => world::WorldConfig::from(layers),
+    };
+    if dump_config {
+        println!("{}", options.config.to_json());
+        process::exit(0);
+    }
+    Ok(options)
+}
+
+fn to_byte(value: f32) -> u8 {
+    (value * 255.0).clamp(0.0, 255.0) as u8
+}
+
+fn height_pixel(cell: &cell::Cell) -> (u8, u8, u8) {
+    let h = to_byte((cell.properties.height.0 + 1.0) / 2.0);
+    (h, h, h)
+}
+
+fn water_pixel(cell: &cell::Cell) -> (u8, u8, u8) {
+    let w = cell.properties.water.0;
+    let (r, g, b) = height_pixel(cell);
+    (
+        to_byte(r as f32 / 255.0 * (1.0 - w)),
+        to_byte(g as f32 / 255.0 * (1.0 - w)),
+        to_byte(b as f32 / 255.0 * (1.0 - w) + w * 5.0),
+    )
+}
+
+fn air_pressure_pixel(cell: &cell::Cell) -> (u8, u8, u8) {
+    let p = to_byte(cell.properties.air_pressure.0 / 2.0);
+    (0, p, 0)
+}
+
+fn heat_pixel(cell: &cell::Cell) -> (u8, u8, u8) {
+    let h = (cell.properties.heat.0 + 1.0) / 2.0;
+    (to_byte(h), 0, to_byte(1.0 - h))
+}
+
+// Sediment over height, brighter where the water carries more
+fn sediment_pixel(cell: &cell::Cell) -> (u8, u8, u8) {
+    let (r, g, b) = height_pixel(cell);
+    let s = (cell.properties.sediment.0 * 200.0).min(1.0);
+    (
+        to_byte(r as f32 / 255.0 * (1.0 - s) + s),
+        to_byte(g as f32 / 255.0 * (1.0 - s) + s * 0.8),
+        to_byte(b as f32 / 255.0 * (1.0 - s)),
+    )
+}
+
+// Humidity as a share of what the air holds at its heat, white where it is
+// saturated
+fn humidity_pixel(cell: &cell::Cell) -> (u8, u8, u8) {
+    let h = cell.properties.humidity.0 / climate::saturation(cell.properties.heat.0);
+    (to_byte(h), to_byte(h), to_byte(0.3 + h))
+}
+
+// Soil moisture in green and groundwater in blue, each as a share of what the
+// ground holds
+fn soil_pixel(cell: &cell::Cell) -> (u8, u8, u8) {
+    (
+        0,
+        to_byte(cell.properties.soil_moisture.0 / soil::SOIL_CAPACITY),
+        to_byte(cell.properties.groundwater.0 / soil::AQUIFER_CAPACITY),
+    )
+}
+
+// Snow and ice over height, white where it lies deep
+fn ice_pixel(cell: &cell::Cell) -> (u8, u8, u8) {
+    let (r, g, b) = height_pixel(cell);
+    let i = ((cell.properties.snow.0 + cell.properties.ice.0) * 100.0).min(1.0);
+    (
+        to_byte(r as f32 / 255.0 * (1.0 - i) + i),
+        to_byte(g as f32 / 255.0 * (1.0 - i) + i),
+        to_byte(b as f32 / 255.0 * (1.0 - i) + i),
+    )
+}
+
+// Nutrients in the soil, from bare brown to rich green
+fn resources_pixel(cell: &cell::Cell) -> (u8, u8, u8) {
+    let r = cell.properties.resources.0 / soil::FERTILE;
+    (
+        to_byte(0.5 * (1.0 - r)),
+        to_byte(0.3 + 0.5 * r),
+        to_byte(0.1),
+    )
+}
+
+fn biome_pixel(cell: &cell::Cell) -> (u8, u8, u8) {
+    biome::tmp_colors(biome::classify_tags(cell.biome_tags))
+}
+
+fn save_layers(world: &world::World, out: &Path, suffix: &str) -> std::io::Result<()> {
+    let layers: [Layer; 10] = [
+        ("height", height_pixel),
+        ("water", water_pixel),
+        ("sediment", sediment_pixel),
+        ("humidity", humidity_pixel),
+        ("soil", soil_pixel),
+        ("resources", resources_pixel),
+        ("ice", ice_pixel),
+        ("air_pressure", air_pressure_pixel),
+        ("heat", heat_pixel),
+        ("biomes", biome_pixel),
+    ];
+    for (name, to_pixel) in layers.iter() {
+        let file = out.join(format!("{}{}.png", name, suffix));
+        world.save_generic(&file.to_string_lossy(), to_pixel)?;
+    }
+    Ok(())
+}
+
+fn run(options: &Options) -> std::io::Result<()> {
+    let out = Path::new(&options.out);
+    std::fs::create_dir_all(out)?;
+
+    let description = world::WorldDescription::from_config(
+        options.grid,
+        options.width,
+        options.height,
+        options.seed,
+        options.stencil,
+        &options.config,
+    );
+    description
+        .heightmap
+        .save_image(&out.join("noise_heightmap.png").to_string_lossy())?;
+    description
+        .heatmap
+        .save_image(&out.join("noise_heatmap.png").to_string_lossy())?;
+    description
+        .waterlevel
+        .save_image(&out.join("noise_waterlevel.png").to_string_lossy())?;
+
+    let erosion = erosion::Erosion::default();
+    let mut world = world::World::from_description(&description)
+        .with_boundary(options.boundary)
+        .with_threads(options.threads)
+        .with_policy(options.policy)
+        .with_water_model(options.water)
+        .with_erosion(erosion::Erosion {
+            erosion_rate: erosion.erosion_rate * options.erosion,
+            deposition_rate: erosion.deposition_rate * options.erosion,
+            ..erosion
+        })
+        .with_talus(options.talus)
+        .with_climate(options.climate)
+        .with_calendar(options.calendar)
+        .with_seasonal_biomes(options.seasonal_biomes)
+        .with_sea_level(options.sea_level, options.sea_level_rate);
+    let mut substeps = 0;
+    for tick in 1..=options.ticks {
+        match world.update(options.dt) {
+            Ok(n) => substeps += n,
+            Err(issue) => {
+                save_layers(&world, out, "")?;
+                eprintln!("halted at {}", issue);
+                process::exit(1);
+            }
+        }
+        if options.every != 0 && tick % options.every == 0 {
+            save_layers(&world, out, &format!("_{:06}", tick))?;
+        }
+    }
+    if substeps > options.ticks {
+        eprintln!(
+            "--dt {} is not stable, the {} ticks took {} substeps",
+            options.dt, options.ticks, substeps
+        );
+    }
+    if world.skipped() > 0.0 {
+        eprintln!(
+            "--dt {} is too large, {} of the {} time units asked for were skipped",
+            options.dt,
+            world.skipped(),
+            f64::from(options.dt) * f64::from(options.ticks)
+        );
+    }
+    if let Some(issue) = world.issue() {
+        eprintln!("last problem fixed by --policy: {}", issue);
+    }
+    save_layers(&world, out, "")
+}
+
+fn main() {
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(err) = run(&options) {
+        eprintln!("failed to write images: {}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,46 @@
+use fractal;
+use perlin;
+use random;
+use simplex;
+use value;
+use worley;
+
+pub trait NoiseSource {
+    // Number of grid cells the source spans along each axis
+    fn size(&self) -> (u32, u32);
+
+    // Samples a point given in grid units
+    fn sample(&self, x: f32, y: f32) -> f32;
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseKind {
+    #[default]
+    Gradient,
+    Simplex,
+    Value,
+    Worley(worley::WorleyMode),
+}
+
+pub fn new_source(
+    kind: NoiseKind,
+    width: u32,
+    height: u32,
+    rng: &mut random::Rng,
+) -> Box<dyn NoiseSource> {
+    match kind {
+        NoiseKind::Gradient => Box::new(perlin::Grid::new(width, height, rng)),
+        NoiseKind::Simplex => Box::new(simplex::Simplex::new(width, height, rng)),
+        NoiseKind::Value => Box::new(value::ValueGrid::new(width, height, rng)),
+        NoiseKind::Worley(mode) => Box::new(worley::Worley::new(width, height, mode, rng)),
+    }
+}
+
+// How one field of a world description is generated
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoiseLayer {
+    pub kind: NoiseKind,
+    pub fractal: fractal::Fractal,
+}
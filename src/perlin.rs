@@ -1,3 +1,5 @@
+use noise;
+use random;
 use utils;
 use vec;
 use vec::Vector;
@@ -10,29 +12,33 @@ pub struct Grid {
 }
 
 impl Grid {
-    pub fn new(width: u32, height: u32) -> Grid {
+    pub fn new(width: u32, height: u32, rng: &mut random::Rng) -> Grid {
         Grid {
-            width: width,
-            height: height,
+            width,
+            height,
             grid: (0..width * height)
-                .map(|_| utils::new_random_vec())
+                .map(|_| utils::new_random_vec(rng))
                 .collect(),
         }
     }
 
-    pub fn point_to_grid(&self, x: f32, y: f32) -> (u32, u32) {
-        (x as u32, y as u32)
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn point_to_grid(&self, x: f32, y: f32) -> (i32, i32) {
+        (x.floor() as i32, y.floor() as i32)
     }
 
     fn calculate_weight(
         &self,
-        grid_abs_x: u32,
-        grid_abs_y: u32,
+        grid_abs_x: i32,
+        grid_abs_y: i32,
         global_x: f32,
         global_y: f32,
     ) -> f32 {
-        let grid_x = grid_abs_x % self.width;
-        let grid_y = grid_abs_y % self.height;
+        let grid_x = grid_abs_x.rem_euclid(self.width as i32) as u32;
+        let grid_y = grid_abs_y.rem_euclid(self.height as i32) as u32;
         let relative_point =
             vec::Vec2f::new(global_x - grid_abs_x as f32, global_y - grid_abs_y as f32);
 
@@ -42,55 +48,35 @@ impl Grid {
         )
     }
 
-    pub fn generate_noise(&self, resolution: u32) -> Noise {
-        let width = resolution * (self.width);
-        let height = resolution * (self.height);
-        let size = width * height;
-        let noise = (0..size)
-            .map(|i| {
-                let global_x = (i % width) as f32 / resolution as f32;
-                let global_y = (i / width) as f32 / resolution as f32;
-
-                let (grid_x, grid_y) = self.point_to_grid(global_x, global_y);
-
-                let offsets = vec::Vec2f::new(global_x - grid_x as f32, global_y - grid_y as f32);
-
-                println!(
-                    "{:?}, {:?}, {:?}, {:?}",
-                    (grid_x, grid_y),
-                    ((grid_x + 1) % self.width, (grid_y + 1) % self.height),
-                    (global_x, global_y),
-                    offsets
-                );
-
-                let weights: Vec<f32> = vec![
-                    self.calculate_weight(grid_x, grid_y, global_x, global_y),
-                    self.calculate_weight(grid_x + 1, grid_y, global_x, global_y),
-                    self.calculate_weight(grid_x, grid_y + 1, global_x, global_y),
-                    self.calculate_weight(grid_x + 1, grid_y + 1, global_x, global_y),
-                ];
-
-                //for (i, t) in offsets.slice().iter().enumerate() {
-                //    let combined = interpolate(weights[i * 2], weights[i * 2 + 1], *t);
-                //    weights.push(combined);
-                //}
-                //match weights.last() {
-                //    Some(x) => *x,
-                //    None => -1.0,
-                //}
-                utils::interpolate(
-                    utils::interpolate(weights[0], weights[1], *offsets.get(0)),
-                    utils::interpolate(weights[2], weights[3], *offsets.get(0)),
-                    *offsets.get(1),
-                )
-            })
-            .collect();
+    // Samples the noise at a point given in grid units, wrapping around the
+    // grid so the result tiles seamlessly.
+    pub fn sample(&self, x: f32, y: f32) -> f32 {
+        let (grid_x, grid_y) = self.point_to_grid(x, y);
 
-        Noise {
-            width: width,
-            height: height,
-            grid: noise,
-        }
+        let offsets = vec::Vec2f::new(x - grid_x as f32, y - grid_y as f32);
+
+        let weights = [
+            self.calculate_weight(grid_x, grid_y, x, y),
+            self.calculate_weight(grid_x + 1, grid_y, x, y),
+            self.calculate_weight(grid_x, grid_y + 1, x, y),
+            self.calculate_weight(grid_x + 1, grid_y + 1, x, y),
+        ];
+
+        utils::interpolate(
+            utils::interpolate(weights[0], weights[1], *offsets.get(0)),
+            utils::interpolate(weights[2], weights[3], *offsets.get(0)),
+            *offsets.get(1),
+        )
+    }
+}
+
+impl noise::NoiseSource for Grid {
+    fn size(&self) -> (u32, u32) {
+        self.size()
+    }
+
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        self.sample(x, y)
     }
 }
 
@@ -102,7 +88,20 @@ pub struct Noise {
 }
 
 impl Noise {
-    pub fn save_image(&self, file: &str) {
+    pub fn from_fn<F>(width: u32, height: u32, f: F) -> Noise
+    where
+        F: Fn(u32, u32) -> f32,
+    {
+        Noise {
+            width,
+            height,
+            grid: (0..width * height)
+                .map(|i| f(i % width, i / width))
+                .collect(),
+        }
+    }
+
+    pub fn save_image(&self, file: &str) -> std::io::Result<()> {
         let buffer: Vec<u8> = self
             .grid
             .iter()
@@ -115,25 +114,17 @@ impl Noise {
                 acc
             });
 
-        let _ = image::save_buffer(
-            &std::path::Path::new(file),
+        image::save_buffer(
+            std::path::Path::new(file),
             &buffer,
             self.width,
             self.height,
             image::RGBA(8),
-        );
+        )
     }
 
-    pub fn get_gradient(&self, x: u32, y: u32) -> vec::Vec2f {
-        let p0 = (
-            (x + self.width - 1) % self.width,
-            (y + self.height - 1) % self.height,
-        );
-        let p1 = ((x + 1) % self.width, (y + 1) % self.height);
-        vec::Vec2f::new(
-            (self.get(p1.0, p0.1) - self.get(p0.0, p0.1)) / 2.0,
-            (self.get(p0.0, p1.1) - self.get(p0.0, p0.1)) / 2.0,
-        )
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
     }
 
     pub fn get(&self, x: u32, y: u32) -> f32 {
@@ -0,0 +1,84 @@
+use boundary;
+use cell;
+use world;
+
+// Buffers `find_oceans` reuses from one substep to the next
+#[derive(Default)]
+pub struct Basins {
+    // Basin of every cell below the sea level, numbered from 1
+    basin: Vec<usize>,
+    queue: Vec<usize>,
+    // Size of every basin and whether it touches an edge
+    sizes: Vec<(usize, bool)>,
+}
+
+// Marks in `ocean` which cells are ocean: the ground is below `sea_level` and
+// connected through other cells below it to an edge of the world. A world
+// without edges, wrapped all around, has its largest basin as its ocean.
+pub fn find_oceans(world: &world::World, sea_level: f32, basins: &mut Basins, ocean: &mut [bool]) {
+    let fields = &world.fields;
+    let Basins {
+        basin,
+        queue,
+        sizes,
+    } = basins;
+    basin.clear();
+    basin.resize(fields.len(), 0);
+    sizes.clear();
+    for start in 0..fields.len() {
+        if basin[start] != 0 || fields.height[start] >= sea_level {
+            continue;
+        }
+        let number = sizes.len() + 1;
+        let (mut size, mut open) = (0, false);
+        basin[start] = number;
+        queue.push(start);
+        while let Some(i) = queue.pop() {
+            size += 1;
+            let (x, y) = (i as u32 % world.width, i as u32 / world.width);
+            for &(dx, dy, _) in world.stencil.links(y) {
+                match world.boundary.resolve(
+                    x as i32 + dx,
+                    y as i32 + dy,
+                    world.width,
+                    world.height,
+                ) {
+                    boundary::Neighbor::Cell(nx, ny) => {
+                        let n = (ny * world.width + nx) as usize;
+                        if basin[n] == 0 && fields.height[n] < sea_level {
+                            basin[n] = number;
+                            queue.push(n);
+                        }
+                    }
+                    boundary::Neighbor::Outside(_) => open = true,
+                }
+            }
+        }
+        sizes.push((size, open));
+    }
+
+    let largest = sizes
+        .iter()
+        .enumerate()
+        .max_by_key(|&(_, &(size, _))| size)
+        .map_or(0, |(k, _)| k + 1);
+    let any_open = sizes.iter().any(|&(_, open)| open);
+    for (ocean, &number) in ocean.iter_mut().zip(basin.iter()) {
+        *ocean = number != 0
+            && if any_open {
+                sizes[number - 1].1
+            } else {
+                number == largest
+            };
+    }
+}
+
+// Fills the ocean cells up to `sea_level`. The sea takes what flows into it
+// and makes up for what evaporates from it.
+pub fn fill(fields: &mut cell::Fields, sea_level: f32) {
+    for i in 0..fields.len() {
+        if fields.ocean[i] {
+            fields.water[i] = (sea_level - fields.height[i]).max(0.0);
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use noise;
+use random;
+use utils;
+use vec;
+
+const SKEW: f32 = 0.366_025_42;
+const UNSKEW: f32 = 0.211_324_87;
+// Brings the output into roughly the same range as the gradient grid noise
+const SCALE: f32 = 45.0;
+
+// Simplex noise on a triangular lattice, which avoids the axis aligned
+// artifacts of the square gradient grid. The lattice is skewed, so unlike the
+// grid based sources it does not tile.
+#[derive(Debug)]
+pub struct Simplex {
+    width: u32,
+    height: u32,
+    permutation: Vec<u8>,
+    gradients: Vec<vec::Vec2f>,
+}
+
+impl Simplex {
+    pub fn new(width: u32, height: u32, rng: &mut random::Rng) -> Simplex {
+        let mut permutation: Vec<u8> = (0..=255).collect();
+        for i in (1..permutation.len()).rev() {
+            let j = rng.next_u32() as usize % (i + 1);
+            permutation.swap(i, j);
+        }
+        Simplex {
+            width,
+            height,
+            permutation,
+            gradients: (0..256).map(|_| utils::new_random_vec(rng)).collect(),
+        }
+    }
+
+    fn gradient(&self, i: i32, j: i32) -> &vec::Vec2f {
+        let hash = self.permutation[(i & 255) as usize] as i32;
+        let index = self.permutation[((hash + j) & 255) as usize];
+        &self.gradients[index as usize]
+    }
+
+    fn corner(&self, i: i32, j: i32, x: f32, y: f32) -> f32 {
+        let t = 0.5 - x * x - y * y;
+        if t <= 0.0 {
+            0.0
+        } else {
+            let (gx, gy) = self.gradient(i, j).xy();
+            t * t * t * t * (gx * x + gy * y)
+        }
+    }
+}
+
+impl noise::NoiseSource for Simplex {
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        let skew = (x + y) * SKEW;
+        let i = (x + skew).floor() as i32;
+        let j = (y + skew).floor() as i32;
+        let unskew = (i + j) as f32 * UNSKEW;
+        let x0 = x - (i as f32 - unskew);
+        let y0 = y - (j as f32 - unskew);
+
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+        let x1 = x0 - i1 as f32 + UNSKEW;
+        let y1 = y0 - j1 as f32 + UNSKEW;
+        let x2 = x0 - 1.0 + 2.0 * UNSKEW;
+        let y2 = y0 - 1.0 + 2.0 * UNSKEW;
+
+        SCALE
+            * (self.corner(i, j, x0, y0)
+                + self.corner(i + i1, j + j1, x1, y1)
+                + self.corner(i + 1, j + 1, x2, y2))
+    }
+}
@@ -0,0 +1,153 @@
+use cell;
+use climate;
+use ice;
+use water;
+use world;
+
+// Most water the soil of a cell holds
+pub const SOIL_CAPACITY: f32 = 0.05;
+// Most water the ground under the soil holds, the water table is at the
+// surface when it is full
+pub const AQUIFER_CAPACITY: f32 = 0.2;
+// Depth of the bottom of the aquifer below the surface
+const AQUIFER_DEPTH: f32 = 0.2;
+// Depth of the water table below which the ground is soaked
+const WATERLOGGED_DEPTH: f32 = 0.02;
+// Part of the surface water the soil still has room for that soaks in per
+// unit of time
+const INFILTRATION_RATE: f32 = 0.1;
+// Part of the soil moisture that seeps down to the groundwater per unit of
+// time
+const PERCOLATION_RATE: f32 = 0.02;
+// Part of the soil moisture plants take up and give off to dry air per unit of
+// time
+const UPTAKE_RATE: f32 = 0.01;
+// Groundwater flowing to a neighbor per unit of time, water table difference
+// and groundwater
+const GROUNDWATER_FLOW: f32 = 0.05;
+
+// Nutrients the rock under a bare cell weathers into per unit of time, doubled
+// where the soil is wet
+const WEATHERING_RATE: f32 = 0.001;
+// Nutrients at which the soil is as rich as weathering makes it
+pub const FERTILE: f32 = 1.0;
+// Part of the nutrients above fertile that are buried out of reach of plants
+// per unit of time, where the water leaves them behind
+const BURIAL_RATE: f32 = 0.05;
+// Part of its nutrients fully growing plants take up per unit of time
+const CONSUMPTION_RATE: f32 = 0.01;
+// Nutrients fully growing plants give back as they die and rot per unit of
+// time
+const DECAY_RATE: f32 = 0.004;
+// Part of the nutrients of a cell dissolved in its water
+const SOLUBILITY: f32 = 0.1;
+// Part of the nutrients of a cell held by the sediment in its water, per unit
+// of sediment
+const SEDIMENT_BINDING: f32 = 10.0;
+// Nutrients a new world starts with
+pub const INITIAL_RESOURCES: f32 = 0.5;
+
+// How much plants grow in a cell, from 0 to 1: where the soil is moist and it
+// is warmer than freezing
+pub fn growth(cell: &cell::CellProperties) -> f32 {
+    let moisture = (cell.soil_moisture.0 / SOIL_CAPACITY).min(1.0);
+    let warmth = ((cell.heat.0 - ice::FREEZING) * 5.0).clamp(0.0, 1.0);
+    moisture * warmth
+}
+
+// Nutrients a cell gains per unit of time: the rock weathers, slower as the
+// soil gets richer, plants take up nutrients as they grow and give part of
+// them back as they rot, and what is left above fertile gets buried
+pub fn fertility(cell: &cell::CellProperties) -> f32 {
+    let growth = growth(cell);
+    let wetness = (cell.soil_moisture.0 / SOIL_CAPACITY).min(1.0);
+    let weathering = WEATHERING_RATE * (1.0 + wetness) * (1.0 - cell.resources.0 / FERTILE);
+    let burial = BURIAL_RATE * (cell.resources.0 - FERTILE).max(0.0);
+    weathering.max(0.0) + DECAY_RATE * growth
+        - CONSUMPTION_RATE * growth * cell.resources.0
+        - burial
+}
+
+// Part of the nutrients of a cell that the water carries away: what is
+// dissolved in it and what sticks to its sediment
+fn mobile_share(fields: &cell::Fields, i: usize) -> f32 {
+    (SOLUBILITY + SEDIMENT_BINDING * fields.sediment[i]).min(1.0)
+}
+
+// Washes the nutrients the water carries of the cells starting at `start`
+// along with it, the way it takes sediment, see `water::carry`
+pub fn wash(
+    world: &world::World,
+    outflow: &[[f32; cell::MAX_LINKS]],
+    delta: f32,
+    start: usize,
+    next: &mut [f32],
+) {
+    let fields = &world.fields;
+    water::carry(world, outflow, delta, start, next, |i| {
+        mobile_share(fields, i) * fields.resources[i]
+    });
+}
+
+// Height of the water table of a cell with the ground at `height`
+pub fn water_table(height: f32, groundwater: f32) -> f32 {
+    height - AQUIFER_DEPTH * (1.0 - groundwater / AQUIFER_CAPACITY)
+}
+
+// Groundwater flowing from `from` to `to` per unit of time and link weight:
+// down the water table, from the groundwater of the higher cell
+pub fn groundwater_flow(from: &cell::NeighborProperties, to: &cell::NeighborProperties) -> f32 {
+    let fall = (water_table(from.height.0, from.groundwater.0)
+        - water_table(to.height.0, to.groundwater.0))
+    .clamp(0.0, 1.0);
+    GROUNDWATER_FLOW * fall * from.groundwater.0
+}
+
+// Whether the ground of a cell is soaked: the soil is full or the water table
+// is close under the surface
+pub fn is_waterlogged(cell: &cell::CellProperties) -> bool {
+    cell.soil_moisture.0 >= 0.9 * SOIL_CAPACITY
+        || water_table(cell.height.0, cell.groundwater.0) >= cell.height.0 - WATERLOGGED_DEPTH
+}
+
+// Largest time step that doesn't drain more groundwater from a cell than it
+// has
+pub fn stable_delta(stencil: cell::Stencil) -> f32 {
+    1.0 / (GROUNDWATER_FLOW * stencil.weight_sum())
+}
+
+// Moves water up and down through the ground of every cell: surface water
+// soaks into the soil, the soil seeps down to the groundwater, plants give the
+// soil moisture off to the air, and groundwater that doesn't fit under the
+// surface comes up as a spring.
+pub fn seep(delta: f32, fields: &mut cell::Fields) {
+    for i in 0..fields.len() {
+        let cell = fields.get(i);
+        let (mut water, mut soil, mut ground) =
+            (cell.water.0, cell.soil_moisture.0, cell.groundwater.0);
+
+        let infiltration =
+            water.min(SOIL_CAPACITY - soil).max(0.0) * (delta * INFILTRATION_RATE).min(1.0);
+        water -= infiltration;
+        soil += infiltration;
+
+        let percolation = (soil * (delta * PERCOLATION_RATE).min(1.0))
+            .min(AQUIFER_CAPACITY - ground)
+            .max(0.0);
+        soil -= percolation;
+        ground += percolation;
+
+        let dryness = (1.0 - cell.humidity.0 / climate::saturation(cell.heat.0)).max(0.0);
+        let uptake = soil * (delta * UPTAKE_RATE * dryness).min(1.0);
+        soil -= uptake;
+
+        let spring = (ground - AQUIFER_CAPACITY).max(0.0);
+        ground -= spring;
+        water += spring;
+
+        fields.water[i] = water;
+        fields.soil_moisture[i] = soil;
+        fields.groundwater[i] = ground;
+        fields.humidity[i] = cell.humidity.0 + uptake;
+    }
+}
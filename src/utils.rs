@@ -1,5 +1,5 @@
 use cfg_if::cfg_if;
-//use rand::Rng;
+use random;
 use std::ops;
 use vec;
 
@@ -19,16 +19,10 @@ cfg_if! {
     }
 }
 
-pub fn new_random_vec() -> vec::Vec2f {
-    //let mut rng = rand::thread_rng();
-    //vec::normalize(&vec::Vec2f::new(
-    //    rng.gen::<f32>() - 0.5,
-    //    rng.gen::<f32>() - 0.5,
-    //))
-    vec::normalize(&vec::Vec2f::new(
-        js_sys::Math::random() as f32 - 0.5,
-        js_sys::Math::random() as f32 - 0.5,
-    ))
+pub fn new_random_vec(rng: &mut random::Rng) -> vec::Vec2f {
+    let x = rng.next_f32() - 0.5;
+    let y = rng.next_f32() - 0.5;
+    vec::normalize(&vec::Vec2f::new(x, y))
 }
 
 pub fn interpolate(a: f32, b: f32, t: f32) -> f32 {
@@ -0,0 +1,51 @@
+use noise;
+use random;
+use utils;
+
+// Value noise: random values on the grid points, smoothly interpolated
+#[derive(Debug)]
+pub struct ValueGrid {
+    width: u32,
+    height: u32,
+    grid: Vec<f32>,
+}
+
+impl ValueGrid {
+    pub fn new(width: u32, height: u32, rng: &mut random::Rng) -> ValueGrid {
+        ValueGrid {
+            width,
+            height,
+            grid: (0..width * height).map(|_| rng.next_f32() - 0.5).collect(),
+        }
+    }
+
+    fn get(&self, x: i32, y: i32) -> f32 {
+        let x = x.rem_euclid(self.width as i32) as u32;
+        let y = y.rem_euclid(self.height as i32) as u32;
+        self.grid[(y * self.width + x) as usize]
+    }
+}
+
+impl noise::NoiseSource for ValueGrid {
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        let (grid_x, grid_y) = (x.floor() as i32, y.floor() as i32);
+        let (offset_x, offset_y) = (x - grid_x as f32, y - grid_y as f32);
+        utils::interpolate(
+            utils::interpolate(
+                self.get(grid_x, grid_y),
+                self.get(grid_x + 1, grid_y),
+                offset_x,
+            ),
+            utils::interpolate(
+                self.get(grid_x, grid_y + 1),
+                self.get(grid_x + 1, grid_y + 1),
+                offset_x,
+            ),
+            offset_y,
+        )
+    }
+}
@@ -1,5 +1,4 @@
 use std::ops;
-use wasm_bindgen::prelude::*;
 
 pub trait Vector<T> {
     fn new(values: &[T]) -> Self;
@@ -9,6 +8,12 @@ pub trait Vector<T> {
     fn get(&self, i: usize) -> &T {
         &self.slice()[i]
     }
+
+    // Applies `f` to every component, without going through the heap
+    fn map<F: Fn(T) -> T>(&self, f: F) -> Self;
+
+    // Applies `f` to every pair of components of `self` and `other`
+    fn zip_map<F: Fn(T, T) -> T>(&self, other: &Self, f: F) -> Self;
 }
 
 pub fn add<T, E>(vec1: &T, vec2: &T) -> T
@@ -16,14 +21,7 @@ where
     E: ops::Add<Output = E> + Copy + Clone,
     T: Vector<E>,
 {
-    let values = vec1.slice();
-    let new_values: Vec<E> = vec2
-        .slice()
-        .iter()
-        .enumerate()
-        .map(|(i, val)| val.add(values[i]))
-        .collect();
-    T::new(&new_values)
+    vec2.zip_map(vec1, |a, b| a.add(b))
 }
 
 pub fn diff<T, E>(vec1: &T, vec2: &T) -> T
@@ -31,14 +29,7 @@ where
     E: ops::Sub<Output = E> + Copy + Clone,
     T: Vector<E>,
 {
-    let values = vec2.slice();
-    let new_values: Vec<E> = vec1
-        .slice()
-        .iter()
-        .enumerate()
-        .map(|(i, val)| val.sub(values[i]))
-        .collect();
-    T::new(&new_values)
+    vec1.zip_map(vec2, |a, b| a.sub(b))
 }
 
 pub fn mul<T, E, S>(scalar: S, vec: &T) -> T
@@ -47,8 +38,7 @@ where
     E: ops::Mul<S, Output = E> + Copy + Clone,
     T: Vector<E>,
 {
-    let values: Vec<E> = vec.slice().iter().map(|x| x.mul(scalar)).collect();
-    T::new(&values)
+    vec.map(|x| x.mul(scalar))
 }
 
 pub fn dot<T: Vector<f32>>(vec1: &T, vec2: &T) -> f32 {
@@ -70,9 +60,9 @@ where
 pub fn normalize<T: Vector<f32>>(vec: &T) -> T {
     let length = len(vec);
     if length == 0.0 {
-        T::new(vec.slice())
+        vec.map(|x| x)
     } else {
-        mul(1.0 / length, &vec)
+        mul(1.0 / length, vec)
     }
 }
 
@@ -97,6 +87,18 @@ impl<T: Copy + Clone> Vector<T> for Vec3<T> {
     fn slice(&self) -> &[T] {
         &self.0
     }
+
+    fn map<F: Fn(T) -> T>(&self, f: F) -> Vec3<T> {
+        Vec3([f(self.0[0]), f(self.0[1]), f(self.0[2])])
+    }
+
+    fn zip_map<F: Fn(T, T) -> T>(&self, other: &Vec3<T>, f: F) -> Vec3<T> {
+        Vec3([
+            f(self.0[0], other.0[0]),
+            f(self.0[1], other.0[1]),
+            f(self.0[2], other.0[2]),
+        ])
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -120,6 +122,14 @@ impl<T: Copy + Clone> Vector<T> for Vec2<T> {
     fn slice(&self) -> &[T] {
         &self.0
     }
+
+    fn map<F: Fn(T) -> T>(&self, f: F) -> Vec2<T> {
+        Vec2([f(self.0[0]), f(self.0[1])])
+    }
+
+    fn zip_map<F: Fn(T, T) -> T>(&self, other: &Vec2<T>, f: F) -> Vec2<T> {
+        Vec2([f(self.0[0], other.0[0]), f(self.0[1], other.0[1])])
+    }
 }
 
 //#[wasm_bindgen]
@@ -0,0 +1,464 @@
+use biome;
+use boundary;
+use cell;
+use climate;
+use diagnostics;
+use erosion;
+use soil;
+use std::cell::RefCell;
+use utils;
+use wasm_bindgen::prelude::*;
+use water;
+use world;
+
+thread_local! {
+    static CURRENT_WORLD: RefCell<Option<world::World>> = const { RefCell::new(None) };
+    static TIMESTEP: RefCell<Option<world::FixedTimestep>> = const { RefCell::new(None) };
+}
+
+fn with_world<T, F>(f: F) -> Option<T>
+where
+    F: FnOnce(&mut world::World) -> T,
+{
+    CURRENT_WORLD.with(|current| current.borrow_mut().as_mut().map(f))
+}
+
+#[wasm_bindgen]
+extern "C" {
+    fn alert(s: &str);
+}
+
+// Number of cells along the width covered by one noise grid cell
+const CELLS_PER_GRID: u32 = 25;
+
+fn grid_size(width: u32) -> u32 {
+    (width / CELLS_PER_GRID).max(1)
+}
+
+#[wasm_bindgen]
+pub fn create(width: u32, height: u32, seed: u32) {
+    utils::set_panic_hook();
+    CURRENT_WORLD.with(|current| {
+        *current.borrow_mut() = Some(world::World::new(grid_size(width), width, height, seed));
+    });
+}
+
+// Like `create`, with cells laid out by the stencil `von-neumann`, `moore` or
+// `hex` and the world fields described by a json `WorldConfig`, or the default
+// fields if `config` is empty
+#[wasm_bindgen]
+pub fn create_from_config(
+    width: u32,
+    height: u32,
+    seed: u32,
+    stencil: &str,
+    config: &str,
+) -> Result<(), JsValue> {
+    utils::set_panic_hook();
+    let stencil = stencil
+        .parse::<cell::Stencil>()
+        .map_err(|err| JsValue::from_str(&err))?;
+    let config = if config.is_empty() {
+        world::WorldConfig::default()
+    } else {
+        world::WorldConfig::from_json(config).map_err(|err| JsValue::from_str(&err.to_string()))?
+    };
+    boundary::Boundary::default()
+        .check(stencil, height)
+        .map_err(|err| JsValue::from_str(&err))?;
+    let description = world::WorldDescription::from_config(
+        grid_size(width),
+        width,
+        height,
+        seed,
+        stencil,
+        &config,
+    );
+    CURRENT_WORLD.with(|current| {
+        *current.borrow_mut() = Some(world::World::from_description(&description));
+    });
+    Ok(())
+}
+
+// Sets the edges of the current world, each one of `wrap`, `closed`, `open`
+// or `inflow:<depth>`
+#[wasm_bindgen]
+pub fn set_boundary(north: &str, south: &str, east: &str, west: &str) -> Result<(), JsValue> {
+    let parse = |edge: &str| {
+        edge.parse::<boundary::Edge>()
+            .map_err(|err| JsValue::from_str(&err))
+    };
+    let boundary = boundary::Boundary {
+        north: parse(north)?,
+        south: parse(south)?,
+        east: parse(east)?,
+        west: parse(west)?,
+    };
+    replace_boundary(boundary)
+}
+
+fn replace_boundary(boundary: boundary::Boundary) -> Result<(), JsValue> {
+    with_world(|world| {
+        boundary.check(world.stencil, world.height)?;
+        world.boundary = boundary;
+        Ok(())
+    })
+    .unwrap_or(Ok(()))
+    .map_err(|err: String| JsValue::from_str(&err))
+}
+
+// Sets the edges of the current world to `torus`, `cylinder`, `closed` or `open`
+#[wasm_bindgen]
+pub fn set_boundary_preset(name: &str) -> Result<(), JsValue> {
+    let boundary = boundary::Boundary::preset(name)
+        .ok_or_else(|| JsValue::from_str(&format!("unknown boundary preset: {}", name)))?;
+    replace_boundary(boundary)
+}
+
+// Sets the neighbors cells of the current world exchange with, `von-neumann`
+// or `moore`. A world can't switch between hex and square cells, its fields
+// are sampled at the cell centers, use `create_from_config` for a new world.
+#[wasm_bindgen]
+pub fn set_stencil(name: &str) -> Result<(), JsValue> {
+    let stencil = name
+        .parse::<cell::Stencil>()
+        .map_err(|err| JsValue::from_str(&err))?;
+    with_world(|world| {
+        if (stencil == cell::Stencil::Hex) != (world.stencil == cell::Stencil::Hex) {
+            return Err(JsValue::from_str(
+                "switching between hex and square cells needs a new world, see create_from_config",
+            ));
+        }
+        world.stencil = stencil;
+        Ok(())
+    })
+    .unwrap_or(Ok(()))
+}
+
+// Sets how water moves in the current world, `pipes` or `flow`
+#[wasm_bindgen]
+pub fn set_water_model(name: &str) -> Result<(), JsValue> {
+    let water_model = name
+        .parse::<water::WaterModel>()
+        .map_err(|err| JsValue::from_str(&err))?;
+    with_world(|world| world.water_model = water_model);
+    Ok(())
+}
+
+// Sets how fast water erodes the ground of the current world, all 0 turns
+// erosion off
+#[wasm_bindgen]
+pub fn set_erosion(capacity: f32, erosion_rate: f32, deposition_rate: f32) -> Result<(), JsValue> {
+    let erosion = erosion::Erosion {
+        capacity,
+        erosion_rate,
+        deposition_rate,
+    };
+    if !erosion.is_valid() {
+        return Err(JsValue::from_str("invalid erosion capacity or rates"));
+    }
+    with_world(|world| world.erosion = erosion);
+    Ok(())
+}
+
+// Sets the steepest slope in degrees the ground of the current world stands at
+// and how fast steeper slopes slump, a rate of 0 turns slumping off
+#[wasm_bindgen]
+pub fn set_talus(angle: f32, rate: f32) -> Result<(), JsValue> {
+    if !(angle > 0.0 && angle < 90.0) || rate.is_nan() || rate < 0.0 {
+        return Err(JsValue::from_str("invalid talus angle or rate"));
+    }
+    with_world(|world| world.talus = erosion::Talus { angle, rate });
+    Ok(())
+}
+
+// Sets the latitudes in degrees of the northern and southern edges of the
+// current world
+#[wasm_bindgen]
+pub fn set_latitudes(north: f32, south: f32) -> Result<(), JsValue> {
+    let climate = climate::Climate {
+        north,
+        south,
+        ..climate::Climate::default()
+    };
+    if !climate.is_valid() {
+        return Err(JsValue::from_str("latitudes must be between -90 and 90"));
+    }
+    with_world(|world| {
+        world.climate.north = north;
+        world.climate.south = south;
+    });
+    Ok(())
+}
+
+// Sets how fast the current world turns, 0 turns the Coriolis force off, and
+// whether its winds lean towards the prevailing winds of a planet
+#[wasm_bindgen]
+pub fn set_winds(rotation: f32, circulation: bool) -> Result<(), JsValue> {
+    if !rotation.is_finite() {
+        return Err(JsValue::from_str("the rotation must be finite"));
+    }
+    with_world(|world| {
+        world.climate.rotation = rotation;
+        world.climate.circulation = circulation;
+    });
+    Ok(())
+}
+
+// Sets the lengths of a day and a year of the current world and the tilt of
+// its axis in degrees, see `climate::Calendar`
+#[wasm_bindgen]
+pub fn set_calendar(day_length: f32, year_length: f32, axial_tilt: f32) -> Result<(), JsValue> {
+    let calendar = climate::Calendar {
+        day_length,
+        year_length,
+        axial_tilt,
+    };
+    if !calendar.is_valid() {
+        return Err(JsValue::from_str("invalid calendar"));
+    }
+    with_world(|world| world.calendar = calendar);
+    Ok(())
+}
+
+// Sets whether biomes follow the seasonal averages of the cells
+#[wasm_bindgen]
+pub fn set_seasonal_biomes(seasonal: bool) {
+    with_world(|world| world.seasonal_biomes = seasonal);
+}
+
+// Part of the day gone by in the current world, 0.5 is noon
+#[wasm_bindgen]
+pub fn time_of_day() -> f32 {
+    with_world(|world| world.calendar.time_of_day(world.time())).unwrap_or(0.0)
+}
+
+#[wasm_bindgen]
+pub fn day_of_year() -> u32 {
+    with_world(|world| world.calendar.day_of_year(world.time())).unwrap_or(0)
+}
+
+// Sets the height of the sea of the current world, none for no sea, and how
+// fast it rises per unit of time, negative to fall
+#[wasm_bindgen]
+pub fn set_sea_level(level: Option<f32>, rate: f32) -> Result<(), JsValue> {
+    if !level.is_none_or(f32::is_finite) || !rate.is_finite() {
+        return Err(JsValue::from_str(
+            "the sea level and its rate must be finite",
+        ));
+    }
+    with_world(|world| {
+        world.sea_level = level;
+        if level.is_none() {
+            world.fields.ocean.fill(false);
+        }
+        world.sea_level_rate = rate;
+    });
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn get_sea_level() -> Option<f32> {
+    with_world(|world| world.sea_level).unwrap_or(None)
+}
+
+#[wasm_bindgen]
+pub fn seed() -> u32 {
+    with_world(|world| world.seed()).unwrap_or(0)
+}
+
+fn issue_to_js(issue: diagnostics::Issue) -> JsValue {
+    JsValue::from_str(&issue.to_string())
+}
+
+// Advances the current world by `dt`, returns the number of substeps it took
+// to stay stable. Fails once the world is halted by a problem in its fields.
+#[wasm_bindgen]
+pub fn tick(dt: f32) -> Result<u32, JsValue> {
+    with_world(|world| world.update(dt))
+        .unwrap_or(Ok(0))
+        .map_err(issue_to_js)
+}
+
+// Time the current world was asked to simulate but skipped because the time
+// steps were too large, see `World::skipped`
+#[wasm_bindgen]
+pub fn skipped_time() -> f64 {
+    with_world(|world| world.skipped()).unwrap_or(0.0)
+}
+
+// Sets what the current world does when its fields go bad: `clamp`,
+// `rollback` or `halt`
+#[wasm_bindgen]
+pub fn set_policy(name: &str) -> Result<(), JsValue> {
+    let policy = name
+        .parse::<diagnostics::Policy>()
+        .map_err(|err| JsValue::from_str(&err))?;
+    with_world(|world| world.policy = policy);
+    Ok(())
+}
+
+// The last problem found in the fields of the current world
+#[wasm_bindgen]
+pub fn get_issue() -> Option<String> {
+    with_world(|world| world.issue().map(|issue| issue.to_string())).and_then(|issue| issue)
+}
+
+// Makes `advance` tick the world in fixed steps of `step`
+#[wasm_bindgen]
+pub fn set_fixed_timestep(step: f32) -> Result<(), JsValue> {
+    if step.is_nan() || step <= 0.0 {
+        return Err(JsValue::from_str("the fixed timestep must be positive"));
+    }
+    TIMESTEP.with(|timestep| *timestep.borrow_mut() = Some(world::FixedTimestep::new(step)));
+    Ok(())
+}
+
+// Adds `elapsed` to the time accumulated for the fixed timestep and ticks the
+// current world for every whole step of it, returns the number of ticks
+#[wasm_bindgen]
+pub fn advance(elapsed: f32) -> Result<u32, JsValue> {
+    TIMESTEP.with(|timestep| {
+        timestep
+            .borrow_mut()
+            .as_mut()
+            .and_then(|timestep| with_world(|world| timestep.advance(world, elapsed)))
+            .unwrap_or(Ok(0))
+            .map_err(issue_to_js)
+    })
+}
+
+#[wasm_bindgen]
+pub fn size() -> Vec<u32> {
+    let (x, y) = with_world(|world| world.size()).unwrap_or((0, 0));
+    vec![x, y]
+}
+
+// Centers of the cells as x, y pairs, in units of the distance between
+// neighboring cells
+#[wasm_bindgen]
+pub fn get_positions() -> Vec<f32> {
+    with_world(|world| {
+        world
+            .positions()
+            .iter()
+            .flat_map(|&(x, y)| vec![x, y])
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+#[wasm_bindgen]
+pub fn get_pixels(
+    draw_height: bool,
+    draw_water: bool,
+    draw_air_pressure: bool,
+    draw_resources: bool,
+    draw_biomes: bool,
+) -> Vec<u8> {
+    with_world(|world| {
+        let (width, height) = world.size();
+        let mut props = vec![0; (width * height * 3) as usize];
+        for (i, cell) in world.cells().enumerate() {
+            let mut r = 125;
+            let mut g = 125;
+            let mut b = 125;
+
+            if draw_height {
+                let h = (cell.properties.height.0 + 1.0) * 255.0 / 2.0;
+                r = h as u8;
+                g = h as u8;
+                b = h as u8;
+            }
+
+            if draw_water {
+                let w = cell.properties.water.0;
+                r = (r as f32 * (1.0 - w)).max(0.0) as u8;
+                b = (b as f32 * (1.0 - w) + 255.0 * w * 5.0).clamp(0.0, 255.0) as u8;
+                g = (g as f32 * (1.0 - w)).max(0.0) as u8;
+            }
+
+            if draw_air_pressure {
+                let mut p = cell.properties.air_pressure.0 / 2.0;
+                p = p * p * p;
+                r = (r as f32 * (1.0 - p)).clamp(0.0, 255.0) as u8;
+                g = (g as f32 * (1.0 - p) + p * 255.0).clamp(0.0, 255.0) as u8;
+                b = (b as f32 * (1.0 - p)).clamp(0.0, 255.0) as u8;
+            }
+
+            if draw_resources {
+                let n = (cell.properties.resources.0 / soil::FERTILE).clamp(0.0, 1.0);
+                r = (r as f32 * (1.0 - n)) as u8;
+                g = (g as f32 * (1.0 - n) + 200.0 * n) as u8;
+                b = (b as f32 * (1.0 - n)) as u8;
+            }
+
+            if draw_biomes {
+                let (br, bg, bb) = biome::tmp_colors(biome::classify_tags(cell.biome_tags));
+                r = br;
+                g = bg;
+                b = bb;
+            }
+
+            props[3 * i] = r;
+            props[3 * i + 1] = g;
+            props[3 * i + 2] = b;
+        }
+        props
+    })
+    .unwrap_or_default()
+}
+
+#[wasm_bindgen]
+pub fn get_heights(with_water: bool) -> Vec<f32> {
+    with_world(|world| {
+        let fields = &world.fields;
+        if with_water {
+            fields
+                .height
+                .iter()
+                .zip(fields.water.iter())
+                .map(|(height, water)| height + water)
+                .collect()
+        } else {
+            fields.height.clone()
+        }
+    })
+    .unwrap_or_default()
+}
+
+#[wasm_bindgen]
+pub fn get_resources() -> Vec<f32> {
+    with_world(|world| world.fields.resources.clone()).unwrap_or_default()
+}
+
+#[wasm_bindgen]
+pub fn get_wind_directions() -> Vec<f32> {
+    with_world(|world| {
+        let mut directions = Vec::with_capacity(world.fields.len() * 2);
+        for wind in world.fields.wind.iter() {
+            let (x, y) = wind.xy();
+            directions.push(*x);
+            directions.push(*y);
+        }
+        directions
+    })
+    .unwrap_or_default()
+}
+
+#[wasm_bindgen]
+pub fn alter_world(
+    center_x: u32,
+    center_y: u32,
+    radius: u32,
+    d_water: f32,
+    d_air_pressure: f32,
+    d_height: f32,
+) {
+    with_world(|world| {
+        let indices = world.select_cells((center_x, center_y), radius);
+        for i in indices.iter() {
+            world.fields.alter(*i, d_water, d_air_pressure, d_height);
+        }
+    });
+}
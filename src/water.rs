@@ -0,0 +1,276 @@
+use boundary;
+use cell;
+use std::str::FromStr;
+use vec;
+use world;
+
+// How water moves between cells
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WaterModel {
+    // Water follows a flow field that every cell computes on its own. Simple,
+    // but it creates and destroys water.
+    Flow,
+    // Virtual pipes along the links between cells, each moving water from
+    // one cell to the other. Water is only added or removed by sources and
+    // sinks: open and inflow edges, evaporation and rain.
+    Pipes,
+}
+
+impl FromStr for WaterModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<WaterModel, String> {
+        match s {
+            "flow" => Ok(WaterModel::Flow),
+            "pipes" => Ok(WaterModel::Pipes),
+            _ => Err(format!("unknown water model: {}", s)),
+        }
+    }
+}
+
+// How fast the flow in a pipe picks up per unit of level difference
+const PIPE_GRAVITY: f32 = 1.0;
+// Part of the flow in a pipe lost per unit of time
+const PIPE_FRICTION: f32 = 0.5;
+
+// Largest time step the pipes stay stable for: waves must not travel further
+// than a cell per step and friction must not reverse the flow
+pub fn stable_delta(stencil: cell::Stencil, max_water: f32) -> f32 {
+    let wave_speed = (PIPE_GRAVITY * max_water).sqrt();
+    (1.0 / PIPE_FRICTION).min(1.0 / (stencil.reach() * wave_speed))
+}
+
+// Water level just outside an edge, for a cell standing on `ground`. None if
+// no water goes through the edge.
+fn ghost_level(edge: boundary::Edge, ground: f32) -> Option<f32> {
+    match edge {
+        boundary::Edge::Wrap | boundary::Edge::Closed => None,
+        boundary::Edge::Open => Some(ground),
+        boundary::Edge::Inflow(depth) => Some(ground + depth),
+    }
+}
+
+// Water coming in over an edge per unit of time through a link of `weight`,
+// for a cell standing on `ground` with its water up to `level`. Edges don't
+// keep a flow, they give what a pipe settles at.
+fn edge_inflow(edge: boundary::Edge, ground: f32, level: f32, weight: f32) -> f32 {
+    match ghost_level(edge, ground) {
+        Some(other) => PIPE_GRAVITY * weight * (other - level).max(0.0) / PIPE_FRICTION,
+        None => 0.0,
+    }
+}
+
+// Speeds up the outflows of the cells starting at `start` by the differences
+// in water level to their neighbors, then scales them down so that no cell
+// loses more water in `delta` than it has
+pub fn outflows(
+    world: &world::World,
+    delta: f32,
+    start: usize,
+    next: &mut [[f32; cell::MAX_LINKS]],
+) {
+    let fields = &world.fields;
+    let damping = (1.0 - delta * PIPE_FRICTION).max(0.0);
+    for (j, outflow) in next.iter_mut().enumerate() {
+        let i = start + j;
+        let (x, y) = (i as u32 % world.width, i as u32 / world.width);
+        let level = fields.height[i] + fields.water[i];
+        let mut total = 0.0;
+        *outflow = [0.0; cell::MAX_LINKS];
+        for (k, &(dx, dy, weight)) in world.stencil.links(y).iter().enumerate() {
+            let other = match world.boundary.resolve(
+                x as i32 + dx,
+                y as i32 + dy,
+                world.width,
+                world.height,
+            ) {
+                boundary::Neighbor::Cell(nx, ny) => {
+                    let n = (ny * world.width + nx) as usize;
+                    Some(fields.height[n] + fields.water[n])
+                }
+                boundary::Neighbor::Outside(edge) => ghost_level(edge, fields.height[i]),
+            };
+            if let Some(other) = other {
+                outflow[k] = (fields.outflow[i][k] * damping
+                    + delta * PIPE_GRAVITY * weight * (level - other))
+                    .max(0.0);
+                total += outflow[k];
+            }
+        }
+        if total * delta > fields.water[i] {
+            let scale = fields.water[i] / (total * delta);
+            for flow in outflow.iter_mut() {
+                *flow *= scale;
+            }
+        }
+    }
+}
+
+// Moves the water of the cells starting at `start` along the `outflow` of the
+// whole world: each cell loses its own outflows and gains the outflows of its
+// neighbors that lead back to it, plus what comes in over inflow edges. Also
+// sets the water flow to the net flow through the cell.
+pub fn transport(
+    world: &world::World,
+    outflow: &[[f32; cell::MAX_LINKS]],
+    delta: f32,
+    start: usize,
+    next: (&mut [f32], &mut [vec::Vec2f]),
+) {
+    let fields = &world.fields;
+    let (water, water_flow) = next;
+    for j in 0..water.len() {
+        let i = start + j;
+        let (x, y) = (i as u32 % world.width, i as u32 / world.width);
+        let links = world.stencil.links(y);
+        let (mut change, mut flow_x, mut flow_y, mut norm) = (0.0, 0.0, 0.0, 0.0);
+        for (k, &(dx, dy, weight)) in links.iter().enumerate() {
+            let inflow = match world.boundary.resolve(
+                x as i32 + dx,
+                y as i32 + dy,
+                world.width,
+                world.height,
+            ) {
+                boundary::Neighbor::Cell(nx, ny) => {
+                    outflow[(ny * world.width + nx) as usize][world.stencil.reverse(y, k)]
+                }
+                boundary::Neighbor::Outside(edge) => edge_inflow(
+                    edge,
+                    fields.height[i],
+                    fields.height[i] + fields.water[i],
+                    weight,
+                ),
+            };
+            let net = outflow[i][k] - inflow;
+            let offset = world.stencil.offset(y, dx, dy);
+            let (ox, oy) = offset.xy();
+            change -= net;
+            flow_x += net * ox;
+            flow_y += net * oy;
+            norm += ox * ox + oy * oy;
+        }
+        // Rounding can leave a tiny negative amount where a cell is emptied,
+        // NaN is left for the diagnostics to find
+        let w = fields.water[i] + delta * change;
+        water[j] = if w < 0.0 { 0.0 } else { w };
+        water_flow[j] = vec::Vec2f::new(2.0 * flow_x / norm, 2.0 * flow_y / norm);
+    }
+}
+
+// Moves an amount the water carries of the cells starting at `start` along
+// with it, the part of the water leaving through each link in `outflow`
+// taking the same part of `amount`. Builds on the values the cells stepped to
+// in `next`. What leaves over an open edge is lost.
+pub fn carry<F>(
+    world: &world::World,
+    outflow: &[[f32; cell::MAX_LINKS]],
+    delta: f32,
+    start: usize,
+    next: &mut [f32],
+    amount: F,
+) where
+    F: Fn(usize) -> f32,
+{
+    let water = &world.fields.water;
+    let carried = |i: usize, k: usize| {
+        if water[i] > 0.0 {
+            amount(i) * delta * outflow[i][k] / water[i]
+        } else {
+            0.0
+        }
+    };
+    for (j, value) in next.iter_mut().enumerate() {
+        let i = start + j;
+        let (x, y) = (i as u32 % world.width, i as u32 / world.width);
+        let mut left = *value;
+        for (k, &(dx, dy, _)) in world.stencil.links(y).iter().enumerate() {
+            left -= carried(i, k);
+            if let boundary::Neighbor::Cell(nx, ny) =
+                world
+                    .boundary
+                    .resolve(x as i32 + dx, y as i32 + dy, world.width, world.height)
+            {
+                left += carried(
+                    (ny * world.width + nx) as usize,
+                    world.stencil.reverse(y, k),
+                );
+            }
+        }
+        *value = if left < 0.0 { 0.0 } else { left };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Water going in and out over the edges of the world per unit of time,
+    // with the outflows of one pipe step
+    fn edge_flows(world: &world::World, outflow: &[[f32; cell::MAX_LINKS]]) -> (f64, f64) {
+        let fields = &world.fields;
+        let (mut inflow, mut lost) = (0.0, 0.0);
+        for (i, outflow) in outflow.iter().enumerate() {
+            let (x, y) = (i as u32 % world.width, i as u32 / world.width);
+            for (k, &(dx, dy, weight)) in world.stencil.links(y).iter().enumerate() {
+                if let boundary::Neighbor::Outside(edge) =
+                    world
+                        .boundary
+                        .resolve(x as i32 + dx, y as i32 + dy, world.width, world.height)
+                {
+                    let level = fields.height[i] + fields.water[i];
+                    inflow += f64::from(edge_inflow(edge, fields.height[i], level, weight));
+                    lost += f64::from(outflow[k]);
+                }
+            }
+        }
+        (inflow, lost)
+    }
+
+    #[test]
+    fn edges_balance_inflow_and_outflow() {
+        let stencils = [
+            cell::Stencil::VonNeumann,
+            cell::Stencil::Moore,
+            cell::Stencil::Hex,
+        ];
+        let boundary = boundary::Boundary {
+            west: boundary::Edge::Inflow(0.3),
+            east: boundary::Edge::Open,
+            ..boundary::Boundary::uniform(boundary::Edge::Closed)
+        };
+        let delta = 0.1;
+        for &stencil in stencils.iter() {
+            let mut world = world::World::new(4, 20, 20, 6)
+                .with_stencil(stencil)
+                .with_boundary(boundary);
+            let (mut total_in, mut total_lost) = (0.0, 0.0);
+            for _ in 0..100 {
+                let mut outflow = vec![[0.0; cell::MAX_LINKS]; world.fields.len()];
+                outflows(&world, delta, 0, &mut outflow);
+                let mut water = world.fields.water.clone();
+                let mut water_flow = world.fields.water_flow.clone();
+                transport(&world, &outflow, delta, 0, (&mut water, &mut water_flow));
+
+                let (inflow, lost) = edge_flows(&world, &outflow);
+                let sum = |water: &[f32]| water.iter().map(|&w| f64::from(w)).sum::<f64>();
+                let (before, after) = (sum(&world.fields.water), sum(&water));
+                let expected = before + f64::from(delta) * (inflow - lost);
+                assert!(
+                    (after - expected).abs() < 1e-4 * before,
+                    "{:?}: {} -> {}, expected {}",
+                    stencil,
+                    before,
+                    after,
+                    expected
+                );
+                total_in += inflow;
+                total_lost += lost;
+
+                world.fields.water = water;
+                world.fields.water_flow = water_flow;
+                world.fields.outflow = outflow;
+            }
+            assert!(total_in > 0.0 && total_lost > 0.0, "{:?}", stencil);
+        }
+    }
+}
@@ -1,34 +1,215 @@
 use biome;
+use boundary;
 use cell;
-#[allow(dead_code)]
+use climate;
+use diagnostics;
+use erosion;
+use graph;
+use ice;
+use noise;
 use perlin;
-use utils;
+use random;
+use sea;
+use serde_json;
+use soil;
+use vec;
+use water;
 
+// Most substeps a single `update` is split into
+pub const MAX_SUBSTEPS: u32 = 64;
+// Most times a substep is rolled back and halved before the world halts
+const MAX_ROLLBACKS: u32 = 4;
+
+// The cells are double buffered: `update` reads `fields` and writes the next
+// state into `back`, then swaps the two, so stepping the cells allocates
+// nothing.
 pub struct World {
-    pub cells: Vec<cell::Cell>,
+    pub fields: cell::Fields,
+    back: cell::Fields,
     pub width: u32,
     pub height: u32,
+    pub boundary: boundary::Boundary,
+    pub stencil: cell::Stencil,
+    // Number of threads `update` steps the cells on, only used on native targets
+    pub threads: usize,
+    // What happens when the fields go bad, they are checked after every substep
+    pub policy: diagnostics::Policy,
+    pub water_model: water::WaterModel,
+    pub erosion: erosion::Erosion,
+    pub talus: erosion::Talus,
+    pub climate: climate::Climate,
+    pub calendar: climate::Calendar,
+    // Whether biomes follow the seasonal averages of the cells rather than
+    // their current state
+    pub seasonal_biomes: bool,
+    // Height of the sea, None for a world without one
+    pub sea_level: Option<f32>,
+    // How fast the sea rises, or falls when negative, per unit of time
+    pub sea_level_rate: f32,
+    basins: sea::Basins,
+    seed: u32,
+    ticks: u64,
+    time: f64,
+    skipped: f64,
+    issue: Option<diagnostics::Issue>,
+    halted: bool,
 }
 
 pub struct WorldDescription {
+    pub seed: u32,
+    pub stencil: cell::Stencil,
     pub heightmap: perlin::Noise,
     pub heatmap: perlin::Noise,
     pub waterlevel: perlin::Noise,
 }
 
-impl World {
-    pub fn new(grid: u32, size: u32) -> World {
-        let description = WorldDescription {
-            heightmap: perlin::Grid::new(grid, grid).generate_noise(size / grid),
-            heatmap: perlin::Grid::new(grid, grid).generate_noise(size / grid),
-            waterlevel: perlin::Grid::new(grid, grid).generate_noise(size / grid),
+// Which noise each field of the description is generated from
+#[derive(Debug, Default, Copy, Clone)]
+pub struct WorldLayers {
+    pub heightmap: noise::NoiseLayer,
+    pub heatmap: noise::NoiseLayer,
+    pub waterlevel: noise::NoiseLayer,
+}
+
+// The node graphs each field of the description is generated from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorldConfig {
+    pub heightmap: graph::Node,
+    pub heatmap: graph::Node,
+    pub waterlevel: graph::Node,
+}
+
+impl From<WorldLayers> for WorldConfig {
+    fn from(layers: WorldLayers) -> WorldConfig {
+        WorldConfig {
+            heightmap: graph::Node::noise(layers.heightmap),
+            heatmap: graph::Node::noise(layers.heatmap),
+            waterlevel: graph::Node::Add {
+                inputs: vec![
+                    graph::Node::noise(layers.waterlevel),
+                    graph::Node::Constant { value: -0.2 },
+                ],
+            },
+        }
+    }
+}
+
+impl Default for WorldConfig {
+    fn default() -> WorldConfig {
+        WorldConfig::from(WorldLayers::default())
+    }
+}
+
+impl WorldConfig {
+    pub fn from_json(json: &str) -> serde_json::Result<WorldConfig> {
+        serde_json::from_str(json)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+impl WorldDescription {
+    // The noise layers are drawn from a single generator in a fixed order, so
+    // the same seed and parameters always give the same description.
+    pub fn new(grid: u32, width: u32, height: u32, seed: u32) -> WorldDescription {
+        WorldDescription::from_config(
+            grid,
+            width,
+            height,
+            seed,
+            cell::Stencil::VonNeumann,
+            &WorldConfig::default(),
+        )
+    }
+
+    // `grid` is the number of noise grid cells along the width, the number
+    // along the height follows from the aspect ratio. The grid cells are as
+    // close to square as a whole number of them along the height allows, so
+    // the grid covers both axes exactly and the noise wraps with the world.
+    // The noise is sampled at the cell centers given by `stencil`.
+    pub fn from_config(
+        grid: u32,
+        width: u32,
+        height: u32,
+        seed: u32,
+        stencil: cell::Stencil,
+        config: &WorldConfig,
+    ) -> WorldDescription {
+        let extent = height as f32 * stencil.row_spacing();
+        let cell_width = width as f32 / grid as f32;
+        let grid_height = ((extent / cell_width).round() as u32).max(1);
+        let cell_height = extent / grid_height as f32;
+        let mut rng = random::Rng::new(seed);
+        let mut generate = |node: &graph::Node| {
+            let source = node.build(grid, grid_height, &mut rng);
+            perlin::Noise::from_fn(width, height, |x, y| {
+                let (px, py) = stencil.position(x as i32, y as i32);
+                source.sample(px / cell_width, py / cell_height)
+            })
         };
+        let heightmap = generate(&config.heightmap);
+        let heatmap = generate(&config.heatmap);
+        let waterlevel = generate(&config.waterlevel);
+        WorldDescription {
+            seed,
+            stencil,
+            heightmap,
+            heatmap,
+            waterlevel,
+        }
+    }
+}
+
+impl World {
+    pub fn new(grid: u32, width: u32, height: u32, seed: u32) -> World {
+        World::from_description(&WorldDescription::new(grid, width, height, seed))
+    }
+
+    pub fn from_description(description: &WorldDescription) -> World {
+        let (width, height) = description.heightmap.size();
+        let fields = cell::Fields::new(description);
         World {
-            cells: (0..size * size)
-                .map(|i| cell::Cell::new(&description, i % size, i / size))
-                .collect(),
-            width: size,
-            height: size,
+            back: fields.clone(),
+            fields,
+            width,
+            height,
+            boundary: boundary::Boundary::default(),
+            stencil: description.stencil,
+            threads: 1,
+            policy: diagnostics::Policy::Halt,
+            water_model: water::WaterModel::Pipes,
+            erosion: erosion::Erosion::default(),
+            talus: erosion::Talus::default(),
+            climate: climate::Climate::default(),
+            calendar: climate::Calendar::default(),
+            seasonal_biomes: false,
+            sea_level: None,
+            sea_level_rate: 0.0,
+            basins: sea::Basins::default(),
+            seed: description.seed,
+            ticks: 0,
+            time: 0.0,
+            skipped: 0.0,
+            issue: None,
+            halted: false,
+        }
+    }
+
+    pub fn with_boundary(self, boundary: boundary::Boundary) -> World {
+        World { boundary, ..self }
+    }
+
+    pub fn with_stencil(self, stencil: cell::Stencil) -> World {
+        World { stencil, ..self }
+    }
+
+    pub fn with_threads(self, threads: usize) -> World {
+        World {
+            threads: threads.max(1),
+            ..self
         }
     }
 
@@ -36,63 +217,779 @@ impl World {
         (self.width, self.height)
     }
 
-    pub fn save_generic<T>(&self, file: &str, to_pixel: T)
+    pub fn with_policy(self, policy: diagnostics::Policy) -> World {
+        World { policy, ..self }
+    }
+
+    pub fn with_water_model(self, water_model: water::WaterModel) -> World {
+        World {
+            water_model,
+            ..self
+        }
+    }
+
+    pub fn with_erosion(self, erosion: erosion::Erosion) -> World {
+        World { erosion, ..self }
+    }
+
+    pub fn with_talus(self, talus: erosion::Talus) -> World {
+        World { talus, ..self }
+    }
+
+    pub fn with_climate(self, climate: climate::Climate) -> World {
+        World { climate, ..self }
+    }
+
+    pub fn with_calendar(self, calendar: climate::Calendar) -> World {
+        World { calendar, ..self }
+    }
+
+    pub fn with_seasonal_biomes(self, seasonal_biomes: bool) -> World {
+        World {
+            seasonal_biomes,
+            ..self
+        }
+    }
+
+    pub fn with_sea_level(self, sea_level: Option<f32>, sea_level_rate: f32) -> World {
+        World {
+            sea_level,
+            sea_level_rate,
+            ..self
+        }
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    // Number of updates so far
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    // Simulated time gone by, see `calendar` for days and years
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    // Time updates were asked to simulate but couldn't in MAX_SUBSTEPS stable
+    // substeps, which is not part of `time`
+    pub fn skipped(&self) -> f64 {
+        self.skipped
+    }
+
+    // The last problem found in the fields, if any
+    pub fn issue(&self) -> Option<diagnostics::Issue> {
+        self.issue
+    }
+
+    pub fn cell(&self, i: usize) -> cell::Cell {
+        cell::Cell {
+            properties: self.fields.get(i),
+            biome_tags: self.fields.biome_tags[i],
+            x: i as u32 % self.width,
+            y: i as u32 / self.width,
+        }
+    }
+
+    pub fn cells<'a>(&'a self) -> impl Iterator<Item = cell::Cell> + 'a {
+        (0..self.fields.len()).map(move |i| self.cell(i))
+    }
+
+    // Centers of the cells, as laid out by the stencil
+    pub fn positions(&self) -> Vec<(f32, f32)> {
+        (0..self.fields.len() as u32)
+            .map(|i| {
+                self.stencil
+                    .position((i % self.width) as i32, (i / self.width) as i32)
+            })
+            .collect()
+    }
+
+    // The cell whose center is closest to a point, if the point is in the world
+    pub fn cell_at(&self, x: f32, y: f32) -> Option<usize> {
+        let row = y / self.stencil.row_spacing();
+        let mut closest: Option<(f32, usize)> = None;
+        for cy in [row.floor() as i32, row.ceil() as i32].iter() {
+            let shift = self.stencil.position(0, *cy).0;
+            let cx = (x - shift).round() as i32;
+            if *cy < 0 || *cy >= self.height as i32 || cx < 0 || cx >= self.width as i32 {
+                continue;
+            }
+            let (px, py) = self.stencil.position(cx, *cy);
+            let distance = (px - x) * (px - x) + (py - y) * (py - y);
+            if closest.is_none_or(|(d, _)| distance < d) {
+                closest = Some((distance, (*cy as u32 * self.width + cx as u32) as usize));
+            }
+        }
+        closest.map(|(_, i)| i)
+    }
+
+    // Writes an image with one pixel per unit of the grid, so hex worlds keep
+    // their proportions
+    pub fn save_generic<T>(&self, file: &str, to_pixel: T) -> std::io::Result<()>
     where
         T: Fn(&cell::Cell) -> (u8, u8, u8),
     {
-        let buffer: Vec<u8> =
-            self.cells
-                .iter()
-                .map(|cell| to_pixel(cell))
-                .fold(vec![], |mut acc, px| {
-                    acc.push(px.0);
-                    acc.push(px.1);
-                    acc.push(px.2);
-                    acc.push(255);
-                    acc
-                });
-
-        let _ = image::save_buffer(
-            &std::path::Path::new(file),
+        let image_width = self.width;
+        let image_height = (self.height as f32 * self.stencil.row_spacing()).ceil() as u32;
+        let mut buffer: Vec<u8> = Vec::with_capacity((image_width * image_height * 4) as usize);
+        for py in 0..image_height {
+            for px in 0..image_width {
+                let pixel = match self.cell_at(px as f32, py as f32) {
+                    Some(i) => to_pixel(&self.cell(i)),
+                    None => (0, 0, 0),
+                };
+                buffer.push(pixel.0);
+                buffer.push(pixel.1);
+                buffer.push(pixel.2);
+                buffer.push(255);
+            }
+        }
+
+        image::save_buffer(
+            std::path::Path::new(file),
             &buffer,
-            self.width,
-            self.height,
+            image_width,
+            image_height,
             image::RGBA(8),
-        );
+        )
+    }
+
+    fn neighbor(
+        &self,
+        me: &cell::NeighborProperties,
+        x: u32,
+        y: u32,
+        dx: i32,
+        dy: i32,
+    ) -> cell::NeighborProperties {
+        match self
+            .boundary
+            .resolve(x as i32 + dx, y as i32 + dy, self.width, self.height)
+        {
+            boundary::Neighbor::Cell(nx, ny) => {
+                self.fields.get_neighbor((ny * self.width + nx) as usize)
+            }
+            boundary::Neighbor::Outside(edge) => {
+                boundary::ghost_cell(edge, me, &self.stencil.offset(y, dx, dy))
+            }
+        }
+    }
+
+    // Steps the cells starting at `start` into `next`. Every cell only reads
+    // the current state, so any split of the cells gives the same result.
+    // What is the same for a whole row is worked out once per row.
+    fn step_cells(&self, delta: f32, start: usize, mut next: cell::FieldsMut) {
+        let rules = cell::Rules {
+            water_model: self.water_model,
+            talus: self.talus,
+            calendar: self.calendar,
+        };
+        let sun = self.calendar.sun(self.time);
+        let width = self.width as usize;
+        for row in 0..next.len() / width {
+            let y = (start / width + row) as u32;
+            let latitude = self.climate.latitude(y, self.height);
+            let prevailing_wind = self.climate.prevailing_wind(latitude);
+            let coriolis = self.climate.coriolis(latitude);
+            let insolation = sun.insolation(latitude);
+            let stencil = self.stencil.links(y);
+            let mut offsets = [vec::Vec2f::new(0.0, 0.0); cell::MAX_LINKS];
+            for (offset, &(dx, dy, _)) in offsets.iter_mut().zip(stencil.iter()) {
+                *offset = self.stencil.offset(y, dx, dy);
+            }
+            for x in 0..width {
+                let j = row * width + x;
+                let i = start + j;
+                let me = self.fields.get_neighbor(i);
+                let mut links = [cell::Link {
+                    offset: vec::Vec2f::new(0.0, 0.0),
+                    weight: 0.0,
+                    properties: me,
+                }; cell::MAX_LINKS];
+                for (k, (link, &(dx, dy, weight))) in
+                    links.iter_mut().zip(stencil.iter()).enumerate()
+                {
+                    *link = cell::Link {
+                        offset: offsets[k],
+                        weight,
+                        properties: self.neighbor(&me, x as u32, y, dx, dy),
+                    };
+                }
+                let neighborhood = cell::Neighborhood {
+                    me,
+                    links: &links[..stencil.len()],
+                    prevailing_wind,
+                    coriolis,
+                    insolation,
+                };
+                let current = self.fields.get(i);
+                let properties = cell::CellProperties::step(&current, delta, &neighborhood, &rules);
+                next.set(j, &properties);
+                next.biome_tags[j] =
+                    biome::tag_cell(&properties, self.seasonal_biomes, self.fields.ocean[i]);
+            }
+        }
+    }
+
+    // Runs `pass` over `out` in bands of whole rows, each with the index of
+    // its first cell, on several threads if `threads` allows
+    fn for_rows<T, F>(&self, out: T, pass: F)
+    where
+        T: Rows,
+        F: Fn(usize, T) + Sync,
+    {
+        if self.threads > 1 {
+            self.for_rows_parallel(out, pass);
+        } else {
+            pass(0, out);
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn for_rows_parallel<T, F>(&self, out: T, pass: F)
+    where
+        T: Rows,
+        F: Fn(usize, T) + Sync,
+    {
+        let rows = (self.height as usize).div_ceil(self.threads);
+        let band = rows * self.width as usize;
+        let pass = &pass;
+        std::thread::scope(|scope| {
+            let (mut rest, mut start) = (out, 0);
+            while rest.len() > band {
+                let (head, tail) = rest.split_at(band);
+                scope.spawn(move || pass(start, head));
+                rest = tail;
+                start += band;
+            }
+            pass(start, rest);
+        });
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn for_rows_parallel<T, F>(&self, out: T, pass: F)
+    where
+        T: Rows,
+        F: Fn(usize, T) + Sync,
+    {
+        pass(0, out);
+    }
+
+    // Largest time step `update` can take in one go for the current state
+    pub fn stable_delta(&self) -> f32 {
+        let fastest =
+            |field: &[vec::Vec2f]| field.iter().fold(0.0, |acc: f32, v| acc.max(vec::len(v)));
+        let wind = fastest(&self.fields.wind);
+        let erosion = if self.erosion.is_none() {
+            f32::INFINITY
+        } else {
+            self.erosion.stable_delta()
+        };
+        let talus = if self.talus.is_none() {
+            f32::INFINITY
+        } else {
+            self.talus.stable_delta(self.stencil)
+        };
+        // The Coriolis force turns the wind by at most a radian per step
+        let rotation = 1.0 / self.climate.rotation.abs();
+        erosion
+            .min(talus)
+            .min(rotation)
+            .min(soil::stable_delta(self.stencil))
+            .min(ice::stable_delta(self.stencil))
+            .min(match self.water_model {
+                water::WaterModel::Flow => {
+                    cell::stable_delta(self.stencil, wind, fastest(&self.fields.water_flow))
+                }
+                water::WaterModel::Pipes => {
+                    let deepest = self
+                        .fields
+                        .water
+                        .iter()
+                        .fold(0.0, |acc: f32, &w| acc.max(w));
+                    cell::stable_delta(self.stencil, wind, 0.0)
+                        .min(water::stable_delta(self.stencil, deepest))
+                }
+            })
+    }
+
+    // Advances the world by `delta`, split into as many equal substeps as
+    // needed to stay stable, and returns the number of substeps taken. The
+    // split is redone after every substep as winds and flows change, up to
+    // MAX_SUBSTEPS in total, and halved for the rest of the update whenever a
+    // substep is rolled back. No substep is ever longer than `stable_delta`,
+    // time left over once the substeps run out is added to `skipped` instead.
+    // Fails if the world is halted by a problem in the fields, see `policy`.
+    pub fn update(&mut self, delta: f32) -> Result<u32, diagnostics::Issue> {
+        if let (true, Some(issue)) = (self.halted, self.issue) {
+            return Err(issue);
+        }
+        self.ticks += 1;
+        let mut remaining = delta;
+        let mut substeps = 0;
+        let mut rollbacks = 0;
+        while remaining > 0.0 && substeps < MAX_SUBSTEPS {
+            let left = (remaining / self.stable_delta()).ceil().max(1.0);
+            let step = remaining / left / (1 << rollbacks) as f32;
+            self.step(step);
+            if let Some(issue) = self.check() {
+                self.issue = Some(issue);
+                match self.policy {
+                    diagnostics::Policy::Clamp => diagnostics::clamp(&mut self.fields),
+                    diagnostics::Policy::Rollback => {
+                        std::mem::swap(&mut self.fields, &mut self.back);
+                        // Smaller substeps can't fix a problem that was there
+                        // before the substep
+                        if rollbacks == MAX_ROLLBACKS || self.check().is_some() {
+                            self.halted = true;
+                            return Err(issue);
+                        }
+                        rollbacks += 1;
+                        continue;
+                    }
+                    diagnostics::Policy::Halt => {
+                        self.halted = true;
+                        return Err(issue);
+                    }
+                }
+            }
+            remaining -= step;
+            substeps += 1;
+            self.time += f64::from(step);
+            if let Some(sea_level) = self.sea_level.as_mut() {
+                *sea_level += self.sea_level_rate * step;
+            }
+        }
+        if remaining > 0.0 {
+            self.skipped += f64::from(remaining);
+        }
+        Ok(substeps)
+    }
+
+    fn check(&self) -> Option<diagnostics::Issue> {
+        diagnostics::check(&self.fields).map(|(i, field, value, problem)| diagnostics::Issue {
+            tick: self.ticks,
+            x: i as u32 % self.width,
+            y: i as u32 / self.width,
+            field,
+            value,
+            problem,
+        })
     }
 
-    pub fn update(&mut self, delta: f32) {
-        let mut updated_cells = vec![];
-        for i in 0..self.cells.len() {
-            let p = (i as u32 % self.width, i as u32 / self.height);
-            let p0 = (
-                (p.0 + self.width - 1) % self.width,
-                (p.1 + self.height - 1) % self.height,
+    fn step(&mut self, delta: f32) {
+        // The cells are tagged by the ocean they start the substep with
+        if let Some(sea_level) = self.sea_level {
+            let mut basins = std::mem::take(&mut self.basins);
+            let mut ocean = std::mem::take(&mut self.fields.ocean);
+            sea::find_oceans(self, sea_level, &mut basins, &mut ocean);
+            self.fields.ocean = ocean;
+            self.basins = basins;
+        }
+        let mut next = std::mem::take(&mut self.back);
+        self.for_rows(next.as_mut(), |start, band| {
+            self.step_cells(delta, start, band)
+        });
+        next.ocean.copy_from_slice(&self.fields.ocean);
+        if self.water_model == water::WaterModel::Pipes {
+            self.for_rows(&mut next.outflow[..], |start, band| {
+                water::outflows(self, delta, start, band)
+            });
+            let outflow = &next.outflow;
+            self.for_rows(
+                (&mut next.water[..], &mut next.water_flow[..]),
+                |start, band| water::transport(self, outflow, delta, start, band),
             );
-            let p1 = ((p.0 + 1) % self.width, (p.1 + 1) % self.height);
-            let neighborhood = cell::Neighborhood {
-                up: self.cells[(p1.1 * self.width + p.0) as usize].properties,
-                down: self.cells[(p0.1 * self.width + p.0) as usize].properties,
-                left: self.cells[(p.1 * self.width + p0.0) as usize].properties,
-                right: self.cells[(p.1 * self.width + p1.0) as usize].properties,
-                me: self.cells[i].properties,
-            };
-            updated_cells.push(self.cells[i].update(delta, &neighborhood));
         }
+        if self.water_model == water::WaterModel::Flow {
+            let water_flow = &next.water_flow;
+            self.for_rows(&mut next.outflow[..], |start, band| {
+                erosion::flow_outflows(self, water_flow, delta, start, band)
+            });
+        }
+        let outflow = &next.outflow;
+        self.for_rows(&mut next.resources[..], |start, band| {
+            soil::wash(self, outflow, delta, start, band)
+        });
+        if !self.erosion.is_none() {
+            let (outflow, water_flow) = (&next.outflow, &next.water_flow);
+            self.for_rows(
+                (&mut next.height[..], &mut next.sediment[..]),
+                |start, band| erosion::transport(self, outflow, water_flow, delta, start, band),
+            );
+        }
+        self.back = std::mem::replace(&mut self.fields, next);
 
-        self.cells = biome::update_biomes(delta, updated_cells);
+        climate::rain(delta, &mut self.fields);
+        soil::seep(delta, &mut self.fields);
+        ice::thaw(delta, &mut self.fields);
+        if let Some(sea_level) = self.sea_level {
+            sea::fill(&mut self.fields, sea_level);
+        }
     }
 
+    // Cells whose centers are within `radius` of the center of the cell at
+    // `center`, wrapping around the edges that wrap
     pub fn select_cells(&self, center: (u32, u32), radius: u32) -> Vec<usize> {
         let mut indices = vec![];
-        for x in -(radius as i32)..(radius as i32) {
-            let r_y = (((radius * radius) as i32 - x * x) as f32).sqrt() as i32;
-            for y in -r_y..r_y {
-                let px = (center.0 as i32 + x + self.width as i32) % (self.width as i32);
-                let py = (center.1 as i32 + y + self.height as i32) % (self.height as i32);
-                indices.push((py * (self.width as i32) + px) as usize);
+        let radius = radius as i32;
+        let rows = (radius as f32 / self.stencil.row_spacing()).ceil() as i32;
+        for dy in -rows..=rows {
+            for dx in -radius - 1..=radius + 1 {
+                let offset = self.stencil.offset(center.1, dx, dy);
+                if vec::len(&offset) >= radius as f32 {
+                    continue;
+                }
+                if let boundary::Neighbor::Cell(px, py) = self.boundary.resolve(
+                    center.0 as i32 + dx,
+                    center.1 as i32 + dy,
+                    self.width,
+                    self.height,
+                ) {
+                    indices.push((py * self.width + px) as usize);
+                }
             }
         }
         indices
     }
 }
+
+// Runs a world in fixed steps of `step` however the elapsed time is sliced,
+// carrying what is left over to the next call. A frontend can call `advance`
+// with the real time of every frame and get the same simulation at any frame
+// rate.
+pub struct FixedTimestep {
+    pub step: f32,
+    // Steps taken at most per call, the rest of a long stall is dropped
+    pub max_steps: u32,
+    accumulated: f32,
+}
+
+impl FixedTimestep {
+    pub fn new(step: f32) -> FixedTimestep {
+        FixedTimestep {
+            step,
+            max_steps: 8,
+            accumulated: 0.0,
+        }
+    }
+
+    // Updates `world` for every whole step in the accumulated time, returns
+    // the number of steps taken
+    pub fn advance(&mut self, world: &mut World, elapsed: f32) -> Result<u32, diagnostics::Issue> {
+        self.accumulated += elapsed.max(0.0);
+        let mut steps = 0;
+        while self.accumulated >= self.step && steps < self.max_steps {
+            world.update(self.step)?;
+            self.accumulated -= self.step;
+            steps += 1;
+        }
+        if steps == self.max_steps {
+            self.accumulated = self.accumulated.min(self.step);
+        }
+        Ok(steps)
+    }
+
+    // How far into the next step the accumulated time is, in [0, 1], for
+    // interpolating between the last two states
+    pub fn alpha(&self) -> f32 {
+        (self.accumulated / self.step).min(1.0)
+    }
+}
+
+// Output of a pass over the cells that can be cut into bands of rows
+trait Rows: Sized + Send {
+    fn len(&self) -> usize;
+
+    fn split_at(self, mid: usize) -> (Self, Self);
+}
+
+impl<'a> Rows for cell::FieldsMut<'a> {
+    fn len(&self) -> usize {
+        cell::FieldsMut::len(self)
+    }
+
+    fn split_at(self, mid: usize) -> (Self, Self) {
+        cell::FieldsMut::split_at(self, mid)
+    }
+}
+
+impl<T: Send> Rows for &mut [T] {
+    fn len(&self) -> usize {
+        <[T]>::len(self)
+    }
+
+    fn split_at(self, mid: usize) -> (Self, Self) {
+        self.split_at_mut(mid)
+    }
+}
+
+impl<A: Rows, B: Rows> Rows for (A, B) {
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn split_at(self, mid: usize) -> (Self, Self) {
+        let (a, a_rest) = self.0.split_at(mid);
+        let (b, b_rest) = self.1.split_at(mid);
+        ((a, b), (a_rest, b_rest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn total_water(fields: &cell::Fields) -> f64 {
+        [
+            &fields.water,
+            &fields.humidity,
+            &fields.soil_moisture,
+            &fields.groundwater,
+            &fields.snow,
+            &fields.ice,
+        ]
+        .iter()
+        .flat_map(|field| field.iter())
+        .map(|&w| f64::from(w))
+        .sum()
+    }
+
+    #[test]
+    fn pipes_conserve_water() {
+        let stencils = [
+            cell::Stencil::VonNeumann,
+            cell::Stencil::Moore,
+            cell::Stencil::Hex,
+        ];
+        let boundaries = [
+            boundary::Boundary::uniform(boundary::Edge::Closed),
+            boundary::Boundary::torus(),
+        ];
+        for &stencil in stencils.iter() {
+            for &boundary in boundaries.iter() {
+                let mut world = World::new(4, 40, 40, 3)
+                    .with_stencil(stencil)
+                    .with_boundary(boundary);
+                let before = total_water(&world.fields);
+                for _ in 0..200 {
+                    world.update(0.2).unwrap();
+                }
+                let after = total_water(&world.fields);
+                assert!(
+                    (after - before).abs() < 1e-4 * before,
+                    "{:?} {:?}: {} -> {}",
+                    stencil,
+                    boundary,
+                    before,
+                    after
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn open_edges_stay_healthy() {
+        for &stencil in [cell::Stencil::Moore, cell::Stencil::Hex].iter() {
+            let mut world = World::new(4, 40, 40, 0)
+                .with_stencil(stencil)
+                .with_boundary(boundary::Boundary::uniform(boundary::Edge::Open))
+                .with_policy(diagnostics::Policy::Halt);
+            for _ in 0..300 {
+                if let Err(problem) = world.update(0.2) {
+                    panic!("{:?}: {}", stencil, problem);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn large_steps_stay_stable_or_are_skipped() {
+        for &delta in [0.2, 10.0, 1000.0].iter() {
+            let mut world = World::new(4, 40, 40, 1).with_policy(diagnostics::Policy::Halt);
+            for _ in 0..10 {
+                if let Err(problem) = world.update(delta) {
+                    panic!("dt {}: {}", delta, problem);
+                }
+            }
+            let asked = 10.0 * f64::from(delta);
+            assert!(
+                (world.time() + world.skipped() - asked).abs() < 1e-4 * asked,
+                "dt {}: {} + {} skipped",
+                delta,
+                world.time(),
+                world.skipped()
+            );
+            assert_eq!(world.skipped() > 0.0, delta > 100.0, "dt {}", delta);
+        }
+    }
+
+    // Puts `value` into `field` of the cell in the middle of the world
+    fn inject(world: &mut World, field: &str, value: f32) {
+        let middle = (world.height / 2 * world.width + world.width / 2) as usize;
+        for (name, values) in world.fields.scalars_mut().iter_mut() {
+            if *name == field {
+                values[middle] = value;
+            }
+        }
+    }
+
+    // Fields a step used to clamp at 0 in a way that hid NaN, with the water
+    // model that steps them
+    const CLAMPED: [(&str, water::WaterModel); 5] = [
+        ("water", water::WaterModel::Flow),
+        ("humidity", water::WaterModel::Pipes),
+        ("groundwater", water::WaterModel::Pipes),
+        ("ice", water::WaterModel::Pipes),
+        ("resources", water::WaterModel::Pipes),
+    ];
+
+    #[test]
+    fn halt_stops_on_bad_values() {
+        for &(field, water_model) in CLAMPED.iter() {
+            let mut world = World::new(4, 20, 20, 2)
+                .with_water_model(water_model)
+                .with_policy(diagnostics::Policy::Halt);
+            inject(&mut world, field, f32::NAN);
+            let issue = world.update(0.2).expect_err(field);
+            assert_eq!(issue.problem, diagnostics::Problem::NotFinite, "{}", field);
+            let again = world.update(0.2).expect_err(field);
+            assert_eq!(
+                (again.tick, again.x, again.y),
+                (issue.tick, issue.x, issue.y)
+            );
+        }
+    }
+
+    #[test]
+    fn clamp_replaces_bad_values() {
+        for &(field, water_model) in CLAMPED.iter() {
+            for &value in [f32::NAN, -1.0].iter() {
+                let mut world = World::new(4, 20, 20, 2)
+                    .with_water_model(water_model)
+                    .with_policy(diagnostics::Policy::Clamp);
+                inject(&mut world, field, value);
+                for _ in 0..5 {
+                    world.update(0.2).unwrap();
+                    assert!(diagnostics::check(&world.fields).is_none(), "{}", field);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rollback_halts_on_bad_values_it_started_with() {
+        for &(field, water_model) in CLAMPED.iter() {
+            let mut world = World::new(4, 20, 20, 2)
+                .with_water_model(water_model)
+                .with_policy(diagnostics::Policy::Rollback);
+            inject(&mut world, field, f32::NAN);
+            let before = world.fields.clone();
+            let issue = world.update(0.2).expect_err(field);
+            assert_eq!(issue.problem, diagnostics::Problem::NotFinite, "{}", field);
+            assert_same_fields(&before, &world.fields);
+        }
+    }
+
+    #[test]
+    fn rows_wrap_without_a_seam() {
+        let worlds = [
+            (cell::Stencil::VonNeumann, 25),
+            (cell::Stencil::Moore, 70),
+            (cell::Stencil::Hex, 30),
+        ];
+        for &(stencil, height) in worlds.iter() {
+            let description =
+                WorldDescription::from_config(4, 40, height, 3, stencil, &WorldConfig::default());
+            let map = &description.heightmap;
+            let jump = |a, b| {
+                (0..40).fold(0.0, |acc: f32, x| {
+                    acc.max((map.get(x, a) - map.get(x, b)).abs())
+                })
+            };
+            let inside = (1..height).fold(0.0, |acc: f32, y| acc.max(jump(y - 1, y)));
+            let wrap = jump(height - 1, 0);
+            assert!(
+                wrap <= 1.5 * inside,
+                "{:?} {}: {} across the wrap, at most {} inside",
+                stencil,
+                height,
+                wrap,
+                inside
+            );
+        }
+    }
+
+    #[test]
+    fn island_mask_is_centered() {
+        let config = WorldConfig {
+            heightmap: graph::Node::IslandMask {
+                radius: 0.2,
+                width: 0.6,
+            },
+            ..WorldConfig::default()
+        };
+        let (width, height) = (40, 25);
+        let description =
+            WorldDescription::from_config(4, width, height, 0, cell::Stencil::VonNeumann, &config);
+        let map = &description.heightmap;
+        // Cells sit on whole coordinates, so the center at (20, 12.5) lies
+        // halfway between (x, y) and (40 - x, 25 - y)
+        for y in 1..height {
+            for x in 1..width {
+                let mirrored = map.get(width - x, height - y);
+                assert!(
+                    (map.get(x, y) - mirrored).abs() < 1e-5,
+                    "({}, {}): {} against {}",
+                    x,
+                    y,
+                    map.get(x, y),
+                    mirrored
+                );
+            }
+        }
+    }
+
+    fn assert_same_fields(a: &cell::Fields, b: &cell::Fields) {
+        for ((name, a), (_, b)) in a.scalars().iter().zip(b.scalars().iter()) {
+            assert!(
+                a.iter()
+                    .zip(b.iter())
+                    .all(|(a, b)| a.to_bits() == b.to_bits()),
+                "{} differs",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn seed_gives_the_same_world() {
+        let run = |seed| {
+            let mut world = World::new(4, 40, 40, seed);
+            for _ in 0..20 {
+                world.update(0.2).unwrap();
+            }
+            world
+        };
+        assert_same_fields(&run(9).fields, &run(9).fields);
+        assert!(run(9).fields.height != run(10).fields.height);
+    }
+
+    #[test]
+    fn threads_give_the_same_world() {
+        let run = |threads| {
+            let mut world = World::new(4, 40, 40, 5).with_threads(threads);
+            for _ in 0..20 {
+                world.update(0.2).unwrap();
+            }
+            world
+        };
+        let single = run(1);
+        for &threads in [3, 8].iter() {
+            assert_same_fields(&single.fields, &run(threads).fields);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use noise;
+use random;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorleyMode {
+    // Peaks at the feature points, falling off with the distance to them
+    Distance,
+    // Ridges along the borders between cells
+    Edges,
+    // A constant random value per cell, giving flat plateaus
+    Cells,
+}
+
+// Cellular noise with one randomly placed feature point per grid cell
+#[derive(Debug)]
+pub struct Worley {
+    width: u32,
+    height: u32,
+    mode: WorleyMode,
+    points: Vec<(f32, f32, f32)>,
+}
+
+impl Worley {
+    pub fn new(width: u32, height: u32, mode: WorleyMode, rng: &mut random::Rng) -> Worley {
+        Worley {
+            width,
+            height,
+            mode,
+            points: (0..width * height)
+                .map(|_| (rng.next_f32(), rng.next_f32(), rng.next_f32() - 0.5))
+                .collect(),
+        }
+    }
+
+    fn point(&self, x: i32, y: i32) -> (f32, f32, f32) {
+        let wrapped_x = x.rem_euclid(self.width as i32) as u32;
+        let wrapped_y = y.rem_euclid(self.height as i32) as u32;
+        let (px, py, value) = self.points[(wrapped_y * self.width + wrapped_x) as usize];
+        (x as f32 + px, y as f32 + py, value)
+    }
+}
+
+impl noise::NoiseSource for Worley {
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        let (grid_x, grid_y) = (x.floor() as i32, y.floor() as i32);
+        let mut closest = (f32::MAX, 0.0);
+        let mut second = f32::MAX;
+        for dy in -1..2 {
+            for dx in -1..2 {
+                let (px, py, value) = self.point(grid_x + dx, grid_y + dy);
+                let distance = ((px - x) * (px - x) + (py - y) * (py - y)).sqrt();
+                if distance < closest.0 {
+                    second = closest.0;
+                    closest = (distance, value);
+                } else if distance < second {
+                    second = distance;
+                }
+            }
+        }
+        match self.mode {
+            WorleyMode::Distance => 0.5 - closest.0,
+            WorleyMode::Edges => (0.5 - 2.0 * (second - closest.0)).max(-0.5),
+            WorleyMode::Cells => closest.1,
+        }
+    }
+}
@@ -4,8 +4,13 @@ import {config, bindBrush} from "./simulationConfig";
 
 const canvas = document.getElementById("canvas");
 
-wasm.create();
+const seedParam = new URLSearchParams(window.location.search).get("seed");
+const seed = seedParam !== null ? Number(seedParam) >>> 0 : Math.floor(Math.random() * 0xffffffff);
+wasm.create_from_config(config.worldWidth, config.worldHeight, seed, config.stencil, "");
+console.log(`world seed: ${wasm.seed()}`);
 const worldSize = wasm.size()
+// Cell centers as x, y pairs, hex rows are shifted and closer together
+const positions = wasm.get_positions();
 
 canvas.width = config.cellSize * worldSize[0];
 canvas.height = config.cellSize * worldSize[1];
@@ -25,38 +30,42 @@ scene.camera.zFar = 300;
 
 bindBrush(canvas, wasm.alter_world);
 
-const pushVertex = (pixels, heights, i, x, y, normal) => {
+const point = (heights, i) => [positions[2*i], heights[i] * 10, positions[2*i+1]];
+
+const pushVertex = (pixels, heights, i, normal) => {
   const r = pixels[3*i]/255;
   const g = pixels[3*i+1]/255;
   const b = pixels[3*i+2]/255;
-  const height = heights[i];
-  va.makeVertex([x , height * 10,y], [r, g, b], normal);
+  va.makeVertex(point(heights, i), [r, g, b], normal);
 }
 
-const generateSquare = (pixels, heights, x, y, top) => {
-  const x1 = x + 1;
-  const y1 = top ? y+1 : y;
-  const x2 = top ? x : x+1;
-  const y2 = y + 1;
-  
+const generateTriangle = (pixels, heights, a, b, c) => {
   const normal = vec3.normalize(vec3.create(), vec3.cross(
         vec3.create(),
-        vec3.sub(vec3.create(), [x2, heights[y2 * worldSize[0] + x2] * 10, y2], [x, heights[y * worldSize[0] + x] * 10, y]),
-        vec3.sub(vec3.create(), [x1, heights[y1 * worldSize[0] + x1] * 10, y1], [x, heights[y * worldSize[0] + x] * 10, y]),
+        vec3.sub(vec3.create(), point(heights, c), point(heights, a)),
+        vec3.sub(vec3.create(), point(heights, b), point(heights, a)),
       ));
 
-  pushVertex(pixels, heights, y * worldSize[0] + x, x, y, normal);
-  pushVertex(pixels, heights, y1 * worldSize[0] + x1, x1, y1, normal);
-  pushVertex(pixels, heights, y2 * worldSize[0] + x2, x2, y2, normal);
-
+  pushVertex(pixels, heights, a, normal);
+  pushVertex(pixels, heights, b, normal);
+  pushVertex(pixels, heights, c, normal);
 }
 
 const generateVa = (pixels, heights) => {
   va.vertexArray = [];
   for (let x = 0; x < worldSize[0] - 1; x++) {
     for (let y = 0; y < worldSize[1] - 1; y++) {
-      generateSquare(pixels, heights, x, y, true);
-      generateSquare(pixels, heights, x, y, false);
+      const i = y * worldSize[0] + x;
+      const below = i + worldSize[0];
+      // Each quad is split along the diagonal between the cells that are
+      // closest, which changes from row to row on a hex grid
+      if (positions[2*below] > positions[2*i]) {
+        generateTriangle(pixels, heights, i, i + 1, below);
+        generateTriangle(pixels, heights, i + 1, below + 1, below);
+      } else {
+        generateTriangle(pixels, heights, i, below + 1, below);
+        generateTriangle(pixels, heights, i, i + 1, below + 1);
+      }
     }
   }
 }
@@ -70,13 +79,26 @@ const drawWorld = pixels => {
 const drawWind = winds => {
 }
 
-const loop = () => {
-  wasm.tick(0.2);
-  generateVa(wasm.get_pixels(config.drawHeight, config.drawWater, config.drawAirPressure, config.drawBiomes), wasm.get_heights(config.drawWater));
-  drawWorld(wasm.get_pixels(config.drawHeight, config.drawWater, config.drawAirPressure, config.drawBiomes));
+wasm.set_fixed_timestep(config.timestep);
+let lastFrame = performance.now();
+
+let halted = false;
+
+const loop = now => {
+  if (!halted) {
+    try {
+      wasm.advance((now - lastFrame) / 1000 * config.speed);
+    } catch (issue) {
+      halted = true;
+      console.error("Simulation halted at", issue);
+    }
+  }
+  lastFrame = now;
+  generateVa(wasm.get_pixels(config.drawHeight, config.drawWater, config.drawAirPressure, config.drawResources, config.drawBiomes), wasm.get_heights(config.drawWater));
+  drawWorld(wasm.get_pixels(config.drawHeight, config.drawWater, config.drawAirPressure, config.drawResources, config.drawBiomes));
   if (config.drawWind)
     drawWind(wasm.get_wind_directions());
   requestAnimationFrame(loop);
 };
 
-loop();
+requestAnimationFrame(loop);
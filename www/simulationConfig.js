@@ -3,8 +3,16 @@ export const config = {
   drawWater: true,
   drawWind: false,
   drawAirPressure: false,
+  drawResources: false,
   drawBiomes: false,
   cellSize: 5,
+  worldWidth: 160,
+  worldHeight: 90,
+  // von-neumann, moore or hex
+  stencil: "von-neumann",
+  // Simulated time per step, and per second of real time
+  timestep: 0.2,
+  speed: 12,
   brush: {
     radius: 15,
     diffWater: 0,
@@ -37,6 +45,7 @@ bindCheckbox("drawHeight", value => config.drawHeight = value, config.drawHeight
 bindCheckbox("drawWater", value => config.drawWater = value, config.drawWater);
 bindCheckbox("drawWind", value => config.drawWind = value, config.drawWind);
 bindCheckbox("drawAirPressure", value => config.drawAirPressure = value, config.drawAirPressure);
+bindCheckbox("drawResources", value => config.drawResources = value, config.drawResources);
 bindCheckbox("drawBiomes", value => config.drawBiomes = value, config.drawBiomes);
 
 bindInput("diffWater", value => config.brush.diffWater = value, config.brush.diffWater);